@@ -1,32 +1,154 @@
-use std::{cell::RefCell, time::SystemTime};
+use std::{
+    cell::RefCell,
+    thread,
+    time::{Duration, SystemTime},
+};
 
 use crate::{
-    utils::Clock, utils::SystemTimeClock, Accelerometer, AccelerometerMeasurement,
-    AccelerometerResult, DeviceConfig, Value,
+    interrupt::{DataReadyLine, GpioDataReadyLine},
+    utils::Clock,
+    utils::SystemTimeClock,
+    Accelerometer, AccelerometerError, AccelerometerMeasurement, AccelerometerResult, DeviceConfig,
+    OutputDataRate, Scale, SupportedChips, Value,
 };
 
 impl Accelerometer {
     /// Create a new instance of the device.
+    ///
+    /// Panics if the device cannot be initialized; see [Accelerometer::try_new].
     pub fn new(config: &DeviceConfig) -> Self {
+        Accelerometer::try_new(config)
+            .unwrap_or_else(|e| panic!("Unable to initialize accelerometer: {}", e))
+    }
+
+    /// Create a new instance of the device, reporting (rather than panicking
+    /// on) failures to open, identify, or configure the chip.
+    pub fn try_new(config: &DeviceConfig) -> AccelerometerResult<Self> {
         let clock = SystemTimeClock {};
-        Accelerometer {
-            chip: config.chip.new(config),
+        let chip = config.chip.new(config)?;
+        let mut accelerometer = Accelerometer {
+            config: DeviceConfig {
+                chip: chip.chip_type(),
+                address: Some(chip.address()),
+                ..config.clone()
+            },
+            chip,
             previous_measurement: RefCell::new(AccelerometerMeasurement::new_default(clock.now())),
             clock: Box::new(clock),
             zero: None,
+            data_ready: None,
+        };
+
+        if let Some(interrupt) = config.interrupt.as_ref() {
+            accelerometer.set_data_ready_line(Box::new(GpioDataReadyLine::new(interrupt)?))?;
         }
+
+        Ok(accelerometer)
     }
 
     #[cfg(test)]
     fn mocked(handle: Box<dyn crate::chips::AccelerometerChip>, clock: Box<dyn Clock>) -> Self {
         Accelerometer {
+            config: DeviceConfig {
+                i2c_device_file: "/dev/null".to_string(),
+                address: Some(handle.address()),
+                chip: handle.chip_type(),
+                scale: Default::default(),
+                data_rate: Default::default(),
+                gyro_scale: Default::default(),
+                magnetometer: false,
+                fifo: false,
+                interrupt: None,
+            },
             chip: handle,
             previous_measurement: RefCell::new(AccelerometerMeasurement::new_default(clock.now())),
             clock: clock,
             zero: None,
+            data_ready: None,
         }
     }
 
+    /// Enables the chip's data-ready interrupt, signalled via `line` (which
+    /// may be a [crate::interrupt::FakeDataReadyLine] in tests)
+    pub fn set_data_ready_line(&mut self, line: Box<dyn DataReadyLine>) -> AccelerometerResult<()> {
+        self.chip.enable_data_ready_interrupt().map(|_| {
+            self.data_ready = Some(RefCell::new(line));
+        })
+    }
+
+    /// Blocks until the chip has a new sample: on the data-ready line, if
+    /// configured, otherwise by sleeping for one sample period
+    pub fn wait_for_data_ready(&self) -> AccelerometerResult<()> {
+        match self.data_ready.as_ref() {
+            Some(line) => line.borrow_mut().wait_for_edge(),
+            None => {
+                thread::sleep(self.chip.sample_period());
+                Ok(())
+            }
+        }
+    }
+
+    /// Blocks until the chip has a new sample (see
+    /// [Accelerometer::wait_for_data_ready]), then measures it
+    pub fn next_measurement(&self) -> AccelerometerResult<AccelerometerMeasurement> {
+        self.wait_for_data_ready().and_then(|_| self.measurement())
+    }
+
+    /// The configuration in effect, with the chip type and address resolved
+    /// (e.g, after [SupportedChips::Auto] detection)
+    pub fn config(&self) -> &DeviceConfig {
+        &self.config
+    }
+
+    /// Time between the chip's samples, at the output data rate it actually
+    /// runs (which may differ from the configured one)
+    pub fn sample_period(&self) -> Duration {
+        self.chip.sample_period()
+    }
+
+    /// The I²C address the chip is being accessed at
+    pub fn address(&self) -> u8 {
+        self.chip.address()
+    }
+
+    /// Changes the full scale range of the running chip.
+    ///
+    /// A zero offset measured at a different scale no longer applies, so it
+    /// is cleared; see [Accelerometer::auto_set_zero].  On failure, the zero
+    /// is kept and the previous scale is written back to the chip (as far as
+    /// the bus allows).
+    pub fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        self.chip.set_scale(scale).map(|_| {
+            if self.config.scale != scale && self.zero.take().is_some() {
+                log::info!(target: "acclrmtr",
+                    "Cleared zero after changing scale from {:?} to {:?}",
+                    self.config.scale,
+                    scale
+                );
+            }
+
+            self.config.scale = scale;
+        })
+    }
+
+    /// Changes the output data rate of the running chip
+    pub fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        self.chip.set_data_rate(data_rate).map(|_| {
+            self.config.data_rate = data_rate;
+        })
+    }
+
+    /// The offset subtracted from each measurement (see
+    /// [Accelerometer::auto_set_zero])
+    pub fn zero(&self) -> Option<Value> {
+        self.zero
+    }
+
+    /// The (detected, if configured as [crate::SupportedChips::Auto]) chip type
+    pub fn chip_type(&self) -> SupportedChips {
+        self.chip.chip_type()
+    }
+
     pub fn auto_set_zero(&mut self) -> AccelerometerResult<Value> {
         let count = 5;
         log::debug!(target: "acclrmtr",
@@ -45,10 +167,17 @@ impl Accelerometer {
 
     pub fn measurement(&self) -> AccelerometerResult<AccelerometerMeasurement> {
         self.get_calibrated_sample().and_then(|value| {
+            let angular_rate = if_supported(self.chip.angular_rate())?;
+            let magnetic_field = if_supported(self.chip.magnetic_field())?;
+            let temperature_c = if_supported(self.chip.temperature())?;
+
             let now = self.clock.as_ref().now();
             let update = AccelerometerMeasurement {
                 time: now,
                 acceleration: value,
+                angular_rate,
+                magnetic_field,
+                temperature_c,
                 estimated_velocity: None, // self.estimate_velocity(now, value),
             };
             self.previous_measurement.replace(update);
@@ -57,6 +186,41 @@ impl Accelerometer {
         })
     }
 
+    /// Reads (and removes) all samples buffered in the chip's hardware FIFO,
+    /// oldest first.  Only the newest sample is timestamped by the clock; the
+    /// others are back-computed from the chip's output data rate.
+    ///
+    /// Requires [crate::DeviceConfig::fifo]; while the FIFO is enabled,
+    /// [Accelerometer::measurement] should not be used, as it competes for
+    /// the same samples.
+    pub fn drain_fifo(&self) -> AccelerometerResult<Vec<AccelerometerMeasurement>> {
+        let samples = self.chip.drain_fifo()?;
+        let period = self.chip.sample_period();
+        let now = self.clock.as_ref().now();
+        let newest = samples.len().saturating_sub(1);
+
+        let measurements: Vec<AccelerometerMeasurement> = samples
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut value)| {
+                if let Some(zero) = self.zero.as_ref() {
+                    value.mut_sub(zero);
+                }
+
+                AccelerometerMeasurement {
+                    acceleration: value,
+                    ..AccelerometerMeasurement::new_default(now - period * (newest - index) as u32)
+                }
+            })
+            .collect();
+
+        if let Some(last) = measurements.last() {
+            self.previous_measurement.replace(*last);
+        }
+
+        Ok(measurements)
+    }
+
     fn get_calibrated_sample(&self) -> AccelerometerResult<Value> {
         self.chip.as_ref().raw_measurement().and_then(|mut value| {
             self.zero.as_ref().map(|zero| {
@@ -90,13 +254,27 @@ impl Accelerometer {
     }
 }
 
+/// Maps [AccelerometerError::NotSupportedByChip] to `None`, for optional
+/// chip capabilities
+fn if_supported<T>(result: AccelerometerResult<T>) -> AccelerometerResult<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(AccelerometerError::NotSupportedByChip { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::Rng;
 
-    use crate::{chips::AccelerometerChip, utils::Clock, Accelerometer, Value};
+    use crate::{
+        chips::AccelerometerChip, interrupt::FakeDataReadyLine, utils::Clock, Accelerometer,
+        AccelerometerError, OutputDataRate, Scale, SupportedChips, Value,
+    };
     use std::{
         cell::RefCell,
+        thread,
         time::{Duration, SystemTime},
     };
 
@@ -166,6 +344,42 @@ mod tests {
             0
         }
 
+        fn address(&self) -> u8 {
+            0
+        }
+
+        fn chip_type(&self) -> SupportedChips {
+            SupportedChips::M845xQ
+        }
+
+        fn sample_period(&self) -> Duration {
+            Duration::from_secs_f64(SECONDS_BETWEEN_MOCK_CLOCK_TICKS)
+        }
+
+        fn enable_data_ready_interrupt(&mut self) -> crate::AccelerometerResult<()> {
+            Ok(())
+        }
+
+        fn set_scale(&mut self, scale: Scale) -> crate::AccelerometerResult<()> {
+            match scale {
+                Scale::SixteenG => Err(AccelerometerError::NotSupportedByChip {
+                    chip: self.chip_type(),
+                    operation: format!("{:?} scale", scale),
+                }),
+                _ => Ok(()),
+            }
+        }
+
+        fn set_data_rate(&mut self, _data_rate: OutputDataRate) -> crate::AccelerometerResult<()> {
+            Ok(())
+        }
+
+        fn drain_fifo(&self) -> crate::AccelerometerResult<Vec<Value>> {
+            let index = self.measurement_index.replace(self.measurements.len());
+
+            Ok(self.measurements[index..].to_vec())
+        }
+
         fn raw_measurement(&self) -> crate::AccelerometerResult<crate::Value> {
             let index = self.measurement_index.replace_with(|prev| *prev + 1);
 
@@ -198,6 +412,8 @@ mod tests {
         let actual = uut.measurement().unwrap();
 
         assert_eq!(actual.acceleration, expected);
+        assert_eq!(actual.angular_rate, None);
+        assert_eq!(actual.temperature_c, None);
     }
 
     #[test]
@@ -216,6 +432,61 @@ mod tests {
         assert_eq!(actual.acceleration, expected);
     }
 
+    #[test]
+    fn drain_fifo() {
+        let mock_handle = MockAccelerometerHandle::new_random(4);
+        let mock_clock: MockClock = Default::default();
+        let start = mock_clock.start;
+        let expected = mock_handle.measurements.clone();
+
+        let uut = Accelerometer::mocked(Box::new(mock_handle), Box::new(mock_clock));
+
+        let actual = uut.drain_fifo().unwrap();
+
+        assert_eq!(actual.len(), expected.len());
+        for (index, measurement) in actual.iter().enumerate() {
+            // The newest sample is taken at the mock clock's second tick
+            // (the first is consumed by Accelerometer::mocked)
+            let ticks_before_newest = (expected.len() - 1 - index) as u32;
+            let expected_time = start + Duration::from_secs_f64(SECONDS_BETWEEN_MOCK_CLOCK_TICKS)
+                - Duration::from_secs_f64(SECONDS_BETWEEN_MOCK_CLOCK_TICKS) * ticks_before_newest;
+
+            assert_eq!(measurement.acceleration, expected[index]);
+            assert_eq!(measurement.time, expected_time);
+        }
+    }
+
+    #[test]
+    fn next_measurement_waits_for_data_ready() {
+        let mock_handle = MockAccelerometerHandle::new_random(1);
+        let mock_clock: MockClock = Default::default();
+        let expected = mock_handle.measurements[0];
+        let (line, edges) = FakeDataReadyLine::new();
+
+        let mut uut = Accelerometer::mocked(Box::new(mock_handle), Box::new(mock_clock));
+        uut.set_data_ready_line(Box::new(line)).unwrap();
+
+        let signaller = thread::spawn(move || edges.send(()).unwrap());
+
+        let actual = uut.next_measurement().unwrap();
+        signaller.join().unwrap();
+
+        assert_eq!(actual.acceleration, expected);
+    }
+
+    #[test]
+    fn wait_for_data_ready_fails_when_line_disconnected() {
+        let mock_handle = MockAccelerometerHandle::new_random(1);
+        let mock_clock: MockClock = Default::default();
+        let (line, edges) = FakeDataReadyLine::new();
+
+        let mut uut = Accelerometer::mocked(Box::new(mock_handle), Box::new(mock_clock));
+        uut.set_data_ready_line(Box::new(line)).unwrap();
+        drop(edges);
+
+        assert!(uut.wait_for_data_ready().is_err());
+    }
+
     #[test]
     #[ignore]
     fn estimate_velocity() {
@@ -249,4 +520,49 @@ mod tests {
 
         assert_eq!(uut.auto_set_zero().unwrap(), avg);
     }
+
+    #[test]
+    fn set_scale_clears_zero() {
+        let mock_handle = MockAccelerometerHandle::new_random(5);
+        let mock_clock: MockClock = Default::default();
+
+        let mut uut = Accelerometer::mocked(Box::new(mock_handle), Box::new(mock_clock));
+        uut.auto_set_zero().unwrap();
+
+        uut.set_scale(Scale::EightG).unwrap();
+
+        assert_eq!(uut.config().scale, Scale::EightG);
+        assert_eq!(uut.zero(), None);
+    }
+
+    #[test]
+    fn set_unsupported_scale_keeps_zero() {
+        let mock_handle = MockAccelerometerHandle::new_random(5);
+        let mock_clock: MockClock = Default::default();
+
+        let mut uut = Accelerometer::mocked(Box::new(mock_handle), Box::new(mock_clock));
+        let zero = uut.auto_set_zero().unwrap();
+        let scale = uut.config().scale;
+
+        assert!(matches!(
+            uut.set_scale(Scale::SixteenG),
+            Err(AccelerometerError::NotSupportedByChip { .. })
+        ));
+        assert_eq!(uut.config().scale, scale);
+        assert_eq!(uut.zero(), Some(zero));
+    }
+
+    #[test]
+    fn set_data_rate_keeps_zero() {
+        let mock_handle = MockAccelerometerHandle::new_random(5);
+        let mock_clock: MockClock = Default::default();
+
+        let mut uut = Accelerometer::mocked(Box::new(mock_handle), Box::new(mock_clock));
+        let zero = uut.auto_set_zero().unwrap();
+
+        uut.set_data_rate(OutputDataRate::DataRate100Hz).unwrap();
+
+        assert_eq!(uut.config().data_rate, OutputDataRate::DataRate100Hz);
+        assert_eq!(uut.zero(), Some(zero));
+    }
 }
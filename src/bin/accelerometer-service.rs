@@ -1,12 +1,32 @@
 extern crate paho_mqtt as mqtt;
 use std::{
-    thread,
-    time::{self, Duration},
+    process,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use accelerometer::mqtt::{QoS, ServiceConfig};
+use accelerometer::{
+    health::Health,
+    http,
+    metrics::Metrics,
+    mqtt::{
+        BatchAccumulator, Command, CommandRequest, CommandResponse, DeviceStatus, OfflineQueue,
+        QueuedMessage, ServiceConfig, TopicConfig, TopicContext,
+    },
+    schedule::{Backoff, Ticker},
+    Accelerometer,
+};
 use clap::Parser;
 
+/// How long to wait for the broker when (re)connecting. Connecting blocks
+/// sampling, so this is kept short (and well under the health check's stall
+/// timeout) for when the broker is unreachable
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Samples to let the chip's FIFO accumulate between drains (by default):
+/// half of the smallest supported FIFO (the MMA8451Q's 32 samples)
+const FIFO_DRAIN_SAMPLES: u32 = 16;
+
 /// Simple program to interact with an accelerometer
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,6 +36,223 @@ struct Args {
     config_file_path: String,
 }
 
+/// Publishes messages, reconnecting (with backoff) when the broker is lost
+/// and holding messages in the (optional) offline queue until it returns
+struct Publisher {
+    client: mqtt::Client,
+
+    conn_opts: mqtt::ConnectOptions,
+
+    backoff: Backoff,
+
+    queue: Option<OfflineQueue>,
+
+    /// Published on every (re)connection (e.g, Home Assistant discovery)
+    announcements: Vec<QueuedMessage>,
+
+    /// Published on every (re)connection, replacing the Last Will
+    birth: Option<QueuedMessage>,
+
+    /// Subscribed to on every (re)connection
+    subscription: Option<TopicConfig>,
+
+    metrics: Arc<Metrics>,
+}
+
+impl Publisher {
+    fn publish(&mut self, message: QueuedMessage) {
+        self.check_connection();
+
+        if self.client.is_connected() && self.replay_queue() {
+            match self.client.publish(to_mqtt(&message)) {
+                Ok(_) => {
+                    self.metrics.record_publish(true);
+                    return;
+                }
+                Err(e) => log::warn!("Unable to publish to {}: {}", message.topic, e),
+            }
+        }
+
+        self.metrics.record_publish(false);
+        self.enqueue(message);
+    }
+
+    /// Reconnects (when the backoff allows) if the connection was lost
+    fn check_connection(&mut self) {
+        if !self.client.is_connected() {
+            self.reconnect();
+        }
+    }
+
+    fn reconnect(&mut self) {
+        let now = Instant::now();
+        if !self.backoff.is_due(now) {
+            return;
+        }
+
+        match self.client.connect(self.conn_opts.clone()) {
+            Ok(_) => {
+                log::info!("Connected to MQTT broker");
+                self.backoff.reset();
+
+                for announcement in self.announcements.iter() {
+                    if let Err(e) = self.client.publish(to_mqtt(announcement)) {
+                        log::warn!("Unable to publish to {}: {}", announcement.topic, e);
+                    }
+                }
+
+                self.publish_birth();
+
+                if let Some(subscription) = self.subscription.as_ref() {
+                    if let Err(e) = self
+                        .client
+                        .subscribe(&subscription.topic, subscription.qos as i32)
+                    {
+                        log::warn!("Unable to subscribe to {}: {}", subscription.topic, e);
+                    }
+                }
+            }
+            Err(e) => {
+                let delay = self.backoff.failed(now);
+                log::warn!(
+                    "Unable to connect to MQTT broker: {}; retrying in {:?}",
+                    e,
+                    delay
+                );
+            }
+        }
+    }
+
+    /// Replaces the birth message, publishing it now if connected
+    fn update_birth(&mut self, birth: Option<QueuedMessage>) {
+        self.birth = birth;
+
+        if self.client.is_connected() {
+            self.publish_birth();
+        }
+    }
+
+    fn publish_birth(&self) {
+        if let Some(birth) = self.birth.as_ref() {
+            if let Err(e) = self.client.publish(to_mqtt(birth)) {
+                log::warn!("Unable to publish status to {}: {}", birth.topic, e);
+            }
+        }
+    }
+
+    /// Publishes queued messages, oldest first, returning whether the queue
+    /// was emptied
+    fn replay_queue(&mut self) -> bool {
+        let queue = match self.queue.as_mut() {
+            Some(queue) if !queue.is_empty() => queue,
+            _ => return true,
+        };
+
+        log::info!("Publishing {} queued message(s)", queue.len());
+
+        while let Some(message) = queue.front() {
+            if let Err(e) = self.client.publish(to_mqtt(message)) {
+                log::warn!("Unable to publish queued message: {}", e);
+                break;
+            }
+
+            queue.pop_front();
+        }
+
+        if let Err(e) = queue.sync() {
+            log::error!("Unable to update offline queue: {}", e);
+        }
+
+        queue.is_empty()
+    }
+
+    fn enqueue(&mut self, message: QueuedMessage) {
+        match self.queue.as_mut() {
+            Some(queue) => {
+                let dropped = queue.dropped();
+
+                if let Err(e) = queue.push(message) {
+                    log::error!("Unable to queue message: {}", e);
+                } else if queue.dropped() > dropped && dropped % 100 == 0 {
+                    log::warn!(
+                        "Offline queue is full; {} message(s) dropped so far",
+                        queue.dropped()
+                    );
+                }
+            }
+            None => log::debug!("Dropping message for {}", message.topic),
+        }
+    }
+}
+
+/// The (retained) online status message, if a status topic is configured
+fn birth(status_topic: Option<&TopicConfig>, acc: &Accelerometer) -> Option<QueuedMessage> {
+    status_topic.map(|status_topic| {
+        status_topic.message(serde_json::to_string(&DeviceStatus::online(acc)).unwrap())
+    })
+}
+
+fn to_mqtt(message: &QueuedMessage) -> mqtt::Message {
+    if message.retain {
+        mqtt::Message::new_retained(&message.topic, message.payload.clone(), message.qos)
+    } else {
+        mqtt::Message::new(&message.topic, message.payload.clone(), message.qos)
+    }
+}
+
+/// Applies the command in `payload`, returning the response to publish and
+/// whether the device's status changed
+fn handle_command(
+    payload: &str,
+    acc: &mut Accelerometer,
+    paused: &mut bool,
+) -> (CommandResponse, bool) {
+    let request = match CommandRequest::parse(payload) {
+        Ok(request) => request,
+        Err(e) => {
+            log::warn!("Ignoring command: {}", e);
+            return (CommandResponse::new(None, Err(e), acc), false);
+        }
+    };
+
+    log::info!("Received command: {:?}", request.command);
+
+    let result = request.command.apply(acc, paused);
+    if let Err(e) = result.as_ref() {
+        log::warn!("Command {:?} failed: {}", request.command, e);
+    }
+
+    let changed = result.is_ok()
+        && matches!(
+            request.command,
+            Command::Rezero | Command::SetScale { .. } | Command::SetDataRate { .. }
+        );
+
+    (CommandResponse::new(request.id, result, acc), changed)
+}
+
+/// Time between published samples: the chip's own sample period when it
+/// paces sampling (via its FIFO or data-ready interrupt), otherwise the
+/// (configured) sample interval
+fn published_sample_period(config: &ServiceConfig, acc: &Accelerometer) -> Duration {
+    if config.device_config.fifo || config.device_config.interrupt.is_some() {
+        acc.sample_period()
+    } else {
+        config.sample_interval(acc.sample_period())
+    }
+}
+
+/// Time between ticks of the sampling loop: with a FIFO, how often it is
+/// drained (the sample interval, by default every [FIFO_DRAIN_SAMPLES]
+/// samples), otherwise one published sample period
+fn tick_interval(config: &ServiceConfig, acc: &Accelerometer) -> Duration {
+    if config.device_config.fifo {
+        config.sample_interval(acc.sample_period() * FIFO_DRAIN_SAMPLES)
+    } else {
+        published_sample_period(config, acc)
+    }
+}
+
 fn main() {
     env_logger::init();
 
@@ -25,41 +262,255 @@ fn main() {
     //       required... if they're missing, we'd rather panic than prompt
     let config = ServiceConfig::load_from_file(&args.config_file_path, false);
 
-    let client = mqtt::Client::new(config.mqtt_config.url).unwrap();
+    let mut acc = Accelerometer::try_new(&config.device_config)
+        .and_then(|mut acc| acc.auto_set_zero().and(Ok(acc)))
+        .unwrap_or_else(|e| {
+            log::error!("Unable to initialize accelerometer: {}", e);
+            process::exit(exitcode::UNAVAILABLE);
+        });
+
+    // With a FIFO, the service drains it on each tick; otherwise, with a
+    // data-ready interrupt, it blocks until each sample is ready (rather than
+    // polling on a ticker)
+    let fifo = config.device_config.fifo;
+    let data_ready = config.device_config.interrupt.is_some() && !fifo;
+    if data_ready && config.sample_interval.is_some() {
+        log::warn!("Ignoring sample_interval: sampling on the data-ready interrupt");
+    }
+    let mut sample_interval = tick_interval(&config, &acc);
+    let mut sample_period = published_sample_period(&config, &acc);
+
+    let topics = TopicContext::new(&acc);
+    let measurement_topic = config.mqtt_config.measurement_topic.resolve(&topics);
+    log::info!("Publishing measurements to {}", measurement_topic.topic);
+
+    let status_topic = config
+        .mqtt_config
+        .status_topic
+        .as_ref()
+        .map(|topic| TopicConfig {
+            retain: true,
+            ..topic.resolve(&topics)
+        });
+
+    let command_topic = config
+        .mqtt_config
+        .command_topic
+        .as_ref()
+        .map(|topic| topic.resolve(&topics));
+    let response_topic = config
+        .mqtt_config
+        .response_topic
+        .as_ref()
+        .map(|topic| topic.resolve(&topics));
 
-    let conn_opts = mqtt::ConnectOptionsBuilder::new()
-        .user_name(config.mqtt_config.user.unwrap())
-        .password(config.mqtt_config.password.unwrap())
+    let client = mqtt::Client::new(config.mqtt_config.url.as_str()).unwrap();
+
+    // NOTE: Consuming must start before connecting, so that no command is
+    //       missed between subscribing and the first poll
+    let commands = client.start_consuming();
+
+    let mut conn_opts = mqtt::ConnectOptionsBuilder::new();
+    conn_opts
+        .user_name(config.mqtt_config.user.clone().unwrap())
+        .password(config.mqtt_config.password.clone().unwrap())
         .keep_alive_interval(Duration::from_secs(20))
-        .clean_session(true)
-        .finalize();
+        .connect_timeout(CONNECT_TIMEOUT)
+        .clean_session(true);
+
+    match config.mqtt_config.ssl_options() {
+        Ok(Some(ssl_opts)) => {
+            conn_opts.ssl_options(ssl_opts);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("Unable to load TLS certificates: {}", e);
+            process::exit(exitcode::CONFIG);
+        }
+    }
 
-    // Connect and wait for it to complete or fail
-    client.connect(conn_opts).unwrap();
+    if let Some(status_topic) = status_topic.as_ref() {
+        let offline = status_topic.message(serde_json::to_string(&DeviceStatus::Offline).unwrap());
+        conn_opts.will_message(to_mqtt(&offline));
+    }
+
+    let conn_opts = conn_opts.finalize();
+
+    let metrics = Arc::new(Metrics::new());
+
+    // The loop is considered stalled after missing several samples (but
+    // never within a few seconds, to allow for reconnecting and re-zeroing)
+    let health = Arc::new(Health::new(
+        Duration::from_secs(10).max(sample_interval * 10),
+    ));
+    health.set_device(acc.chip_type(), acc.address());
+
+    if let Some(http_address) = config.http_address.as_ref() {
+        http::serve(http_address, metrics.clone(), health.clone()).unwrap_or_else(|e| {
+            log::error!("Unable to listen on {}: {}", http_address, e);
+            process::exit(exitcode::UNAVAILABLE);
+        });
+    }
+
+    let queue = config.mqtt_config.offline_queue.as_ref().map(|queue| {
+        OfflineQueue::open(queue).unwrap_or_else(|e| {
+            log::error!("Unable to open offline queue {}: {}", queue.path, e);
+            process::exit(exitcode::CANTCREAT);
+        })
+    });
 
-    let mut acc = accelerometer::Accelerometer::new(&config.device_config);
-    acc.auto_set_zero().unwrap();
+    let mut publisher = Publisher {
+        client,
+        conn_opts,
+        backoff: Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+        queue,
+        announcements: config
+            .home_assistant
+            .as_ref()
+            .map(|home_assistant| {
+                home_assistant.discovery_messages(
+                    &topics,
+                    &measurement_topic,
+                    status_topic.as_ref(),
+                )
+            })
+            .unwrap_or_default(),
+        birth: birth(status_topic.as_ref(), &acc),
+        subscription: command_topic.clone(),
+        metrics: metrics.clone(),
+    };
 
+    // Connect and wait for it to complete or fail (if it fails, we keep
+    // retrying while sampling)
+    publisher.reconnect();
+
+    let mut batcher = config
+        .batch
+        .as_ref()
+        .map(|batch| BatchAccumulator::new(batch, sample_period));
+
+    let mut ticker = Ticker::new(sample_interval);
     let mut sample_count = 0;
+    let mut paused = false;
     loop {
-        let sample = acc.measurement().unwrap();
+        publisher.check_connection();
+        health.heartbeat(SystemTime::now(), publisher.client.is_connected());
+
+        while let Ok(Some(message)) = commands.try_recv() {
+            let (response, changed) = handle_command(&message.payload_str(), &mut acc, &mut paused);
+
+            if let Some(response_topic) = response_topic.as_ref() {
+                publisher
+                    .publish(response_topic.message(serde_json::to_string(&response).unwrap()));
+            }
+
+            if changed {
+                publisher.update_birth(birth(status_topic.as_ref(), &acc));
+
+                // Follow the chip's new sample period, unless the interval was
+                // configured
+                if tick_interval(&config, &acc) != sample_interval {
+                    sample_interval = tick_interval(&config, &acc);
+                    ticker = Ticker::new(sample_interval);
+                }
 
-        let sample_json = serde_json::to_string(&sample).unwrap();
+                if published_sample_period(&config, &acc) != sample_period {
+                    sample_period = published_sample_period(&config, &acc);
 
-        client
-            .publish(mqtt::Message::new(
-                &config.mqtt_config.measurement_topic,
-                sample_json,
-                QoS::AtMostOnce as i32,
-            ))
-            .unwrap();
+                    if let Some(batch) = batcher.as_mut().and_then(|batcher| batcher.flush()) {
+                        publisher.publish(
+                            measurement_topic.message(serde_json::to_string(&batch).unwrap()),
+                        );
+                    }
 
-        if sample_count % 100 == 0 {
-            log::info!("Published sample {:>5}: {:?}", sample_count, sample);
+                    batcher = config
+                        .batch
+                        .as_ref()
+                        .map(|batch| BatchAccumulator::new(batch, sample_period));
+                }
+            }
         }
 
-        sample_count = sample_count + 1;
+        if paused {
+            if let Some(batch) = batcher.as_mut().and_then(|batcher| batcher.gap()) {
+                publisher
+                    .publish(measurement_topic.message(serde_json::to_string(&batch).unwrap()));
+            }
 
-        thread::sleep(time::Duration::from_secs_f64(1.0));
+            ticker.wait();
+            continue;
+        }
+
+        let samples = if fifo {
+            acc.drain_fifo()
+        } else if data_ready {
+            acc.next_measurement().map(|sample| vec![sample])
+        } else {
+            acc.measurement().map(|sample| vec![sample])
+        };
+
+        let samples = match samples {
+            Ok(samples) => {
+                samples
+                    .iter()
+                    .for_each(|sample| metrics.record_sample(sample));
+                health.record_read(
+                    samples
+                        .last()
+                        .map_or_else(SystemTime::now, |sample| sample.time),
+                    true,
+                );
+                samples
+            }
+            Err(e) => {
+                log::error!("Unable to read accelerometer: {}", e);
+                metrics.record_error(&e);
+                health.record_read(SystemTime::now(), false);
+
+                if let Some(batch) = batcher.as_mut().and_then(|batcher| batcher.gap()) {
+                    publisher
+                        .publish(measurement_topic.message(serde_json::to_string(&batch).unwrap()));
+                }
+
+                ticker.wait();
+                continue;
+            }
+        };
+
+        for sample in samples.iter() {
+            let payload = match batcher.as_mut() {
+                Some(batcher) => batcher
+                    .push(sample)
+                    .map(|batch| serde_json::to_string(&batch).unwrap()),
+                None => Some(serde_json::to_string(sample).unwrap()),
+            };
+
+            if let Some(payload) = payload {
+                publisher.publish(measurement_topic.message(payload));
+            }
+
+            if sample_count % 100 == 0 {
+                log::info!("Published sample {:>5}: {:?}", sample_count, sample);
+            }
+
+            sample_count = sample_count + 1;
+        }
+
+        // NOTE: On the data-ready interrupt, the next measurement waits for
+        //       the chip instead
+        if !data_ready {
+            let missed = ticker.wait();
+            if missed > 0 && fifo {
+                // The FIFO keeps buffering (up to its depth) through a late drain
+                log::warn!("Draining the FIFO fell behind by {} tick(s)", missed);
+            } else if missed > 0 {
+                log::warn!("Publishing fell behind; skipped {} sample(s)", missed);
+
+                if let Some(batch) = batcher.as_mut().and_then(|batcher| batcher.gap()) {
+                    publisher
+                        .publish(measurement_topic.message(serde_json::to_string(&batch).unwrap()));
+                }
+            }
+        }
     }
 }
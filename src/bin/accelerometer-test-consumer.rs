@@ -1,10 +1,8 @@
 extern crate paho_mqtt as mqtt;
-use std::time::Duration;
+use std::{process, time::Duration};
 
-use accelerometer::{
-    mqtt::{QoS, ServiceConfig},
-    AccelerometerMeasurement,
-};
+use accelerometer::mqtt::{MeasurementPayload, ServiceConfig};
+use chrono::{DateTime, Utc};
 use clap::Parser;
 
 /// Simple program to interact with an accelerometer
@@ -31,29 +29,42 @@ fn main() {
 
     let config = ServiceConfig::load_from_file(&args.config_file_path, true);
 
-    let client = mqtt::Client::new(config.mqtt_config.url).unwrap();
+    let client = mqtt::Client::new(config.mqtt_config.url.as_str()).unwrap();
 
-    let conn_opts = mqtt::ConnectOptionsBuilder::new()
-        .user_name(config.mqtt_config.user.unwrap())
-        .password(config.mqtt_config.password.unwrap())
+    let mut conn_opts = mqtt::ConnectOptionsBuilder::new();
+    conn_opts
+        .user_name(config.mqtt_config.user.clone().unwrap())
+        .password(config.mqtt_config.password.clone().unwrap())
         .keep_alive_interval(Duration::from_secs(20))
-        .clean_session(true)
-        .finalize();
+        .clean_session(true);
+
+    match config.mqtt_config.ssl_options() {
+        Ok(Some(ssl_opts)) => {
+            conn_opts.ssl_options(ssl_opts);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("Unable to load TLS certificates: {}", e);
+            process::exit(exitcode::CONFIG);
+        }
+    }
+
+    let conn_opts = conn_opts.finalize();
 
     // Connect and wait for it to complete or fail
     client.connect(conn_opts).unwrap();
 
+    // Placeholders (e.g, {hostname}) match any board publishing to the topic
+    let measurement_topic = config.mqtt_config.measurement_topic.subscription_filter();
+
     client
         .subscribe(
-            &config.mqtt_config.measurement_topic,
-            QoS::AtMostOnce as i32,
+            &measurement_topic,
+            config.mqtt_config.measurement_topic.qos as i32,
         )
         .unwrap();
 
-    log::info!(
-        "Subscribed to topic: {}",
-        config.mqtt_config.measurement_topic
-    );
+    log::info!("Subscribed to topic: {}", measurement_topic);
 
     let rx_queue = client.start_consuming();
 
@@ -63,12 +74,22 @@ fn main() {
         sample_count = sample_count + 1;
 
         match msg {
-            Some(msg) => {
-                let sample: AccelerometerMeasurement =
-                    serde_json::from_str(&msg.payload_str()).unwrap();
-
-                log::info!("Received sample {:>5}: {:?}", sample_count, sample);
-            }
+            Some(msg) => match serde_json::from_str(&msg.payload_str()).unwrap() {
+                MeasurementPayload::Single(sample) => {
+                    log::info!("Received sample {:>5}: {:?}", sample_count, sample);
+                }
+                MeasurementPayload::Batch(batch) => {
+                    let start: DateTime<Utc> = batch.start.into();
+
+                    log::info!(
+                        "Received batch  {:>5}: {}: {} samples every {:.5} s",
+                        sample_count,
+                        start.to_rfc3339(),
+                        batch.len(),
+                        batch.sample_period
+                    );
+                }
+            },
             None => {}
         }
 
@@ -1,4 +1,4 @@
-use std::{fs, thread, time};
+use std::{fs, process, thread, time};
 
 use accelerometer::{Accelerometer, DeviceConfig};
 use clap::Parser;
@@ -46,7 +46,10 @@ fn main() {
     let config = fs::read_to_string(args.config_file_path).unwrap();
     let config: Config = serde_yaml::from_str(&config).unwrap();
 
-    let mut acc = Accelerometer::new(&config.device_config);
+    let mut acc = Accelerometer::try_new(&config.device_config).unwrap_or_else(|e| {
+        log::error!("Unable to initialize accelerometer: {}", e);
+        process::exit(exitcode::UNAVAILABLE);
+    });
 
     if !args.no_zero {
         acc.auto_set_zero().unwrap();
@@ -0,0 +1,266 @@
+use std::{cell::RefCell, thread, time};
+
+use embedded_hal::blocking::i2c;
+use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+use crate::{
+    utils, AccelerometerError, AccelerometerResult, DeviceConfig, OutputDataRate, Scale,
+    SupportedChips, Value,
+};
+
+use super::{AccelerometerChip, ChipProbe};
+
+struct ChipConstants;
+impl ChipConstants {
+    // NOTE: The ALT ADDRESS pin selects 0x53 (low) or 0x1d (high)
+    const DEFAULT_I2C_ADDRESS: u8 = 0x53;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x1d;
+
+    const DEVID: u8 = 0x00;
+    const BW_RATE: u8 = 0x2c;
+    const POWER_CTL: u8 = 0x2d;
+    const DATA_FORMAT: u8 = 0x31;
+    const DATAX0: u8 = 0x32;
+
+    const WHO_SHOULD_I_BE: u8 = 0xe5;
+
+    // In full resolution mode, every range is 3.9 mg/LSB
+    const FULL_RES_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND * 0.0039;
+
+    const TWO_G_CFG_BITS: u8 = 0b00;
+    const FOUR_G_CFG_BITS: u8 = 0b01;
+    const EIGHT_G_CFG_BITS: u8 = 0b10;
+    const SIXTEEN_G_CFG_BITS: u8 = 0b11;
+    const DATA_FORMAT_FULL_RES_BITS: u8 = 0b00001000;
+
+    const POWER_CTL_STANDBY_BITS: u8 = 0b00000000;
+    const POWER_CTL_MEASURE_BITS: u8 = 0b00001000;
+
+    const RATE_800HZ_BITS: u8 = 0x0d;
+    const RATE_400HZ_BITS: u8 = 0x0c;
+    const RATE_200HZ_BITS: u8 = 0x0b;
+    const RATE_100HZ_BITS: u8 = 0x0a;
+    const RATE_50HZ_BITS: u8 = 0x09;
+    const RATE_12_5HZ_BITS: u8 = 0x07;
+    const RATE_6_25HZ_BITS: u8 = 0x06;
+    const RATE_1_56HZ_BITS: u8 = 0x04;
+}
+
+const LOG_TARGET: &str = "adxl345";
+
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::ADXL345,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::DEVID,
+    identities: &[ChipConstants::WHO_SHOULD_I_BE],
+};
+
+pub(crate) struct Adxl345Impl<I2C> {
+    i2c: RefCell<I2C>,
+    address: u8,
+    scale: Scale,
+    data_rate: OutputDataRate,
+}
+
+/// The BW_RATE rate code for `data_rate` (every [OutputDataRate] is
+/// supported exactly)
+fn rate_bits(data_rate: &OutputDataRate) -> u8 {
+    match data_rate {
+        OutputDataRate::DataRate800Hz => ChipConstants::RATE_800HZ_BITS,
+        OutputDataRate::DataRate400Hz => ChipConstants::RATE_400HZ_BITS,
+        OutputDataRate::DataRate200Hz => ChipConstants::RATE_200HZ_BITS,
+        OutputDataRate::DataRate100Hz => ChipConstants::RATE_100HZ_BITS,
+        OutputDataRate::DataRate50Hz => ChipConstants::RATE_50HZ_BITS,
+        OutputDataRate::DataRate12_5Hz => ChipConstants::RATE_12_5HZ_BITS,
+        OutputDataRate::DataRate6_25Hz => ChipConstants::RATE_6_25HZ_BITS,
+        OutputDataRate::DataRate1_56Hz => ChipConstants::RATE_1_56HZ_BITS,
+    }
+}
+
+/// The DATA_FORMAT value for `scale`, in full resolution mode
+fn data_format_bits(scale: &Scale) -> u8 {
+    let range_bits = match scale {
+        Scale::TwoG => ChipConstants::TWO_G_CFG_BITS,
+        Scale::FourG => ChipConstants::FOUR_G_CFG_BITS,
+        Scale::EightG => ChipConstants::EIGHT_G_CFG_BITS,
+        Scale::SixteenG => ChipConstants::SIXTEEN_G_CFG_BITS,
+    };
+
+    ChipConstants::DATA_FORMAT_FULL_RES_BITS | range_bits
+}
+
+fn to_meters_per_second(buffer: &[u8]) -> f64 {
+    let value = i16::from_le_bytes([buffer[0], buffer[1]]);
+
+    ChipConstants::FULL_RES_SCALE_FACTOR * value as f64
+}
+
+impl<I2C> Adxl345Impl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
+        let mut chip = Adxl345Impl {
+            i2c: RefCell::new(i2c),
+            address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
+            scale: config.scale,
+            data_rate: config.data_rate,
+        };
+
+        chip.verify_identity()
+            .and_then(|_| chip.standby())
+            .and_then(|_| chip.update_scale())
+            .and_then(|_| chip.update_data_rate())
+            .and_then(|_| chip.measure())?;
+
+        Ok(chip)
+    }
+
+    fn verify_identity(&self) -> AccelerometerResult<()> {
+        self.read_register(ChipConstants::DEVID, "DEVID")
+            .and_then(|devid| {
+                if devid != ChipConstants::WHO_SHOULD_I_BE {
+                    Err(AccelerometerError::IdentityMismatch {
+                        chip: SupportedChips::ADXL345,
+                        address: self.address,
+                        expected: &[ChipConstants::WHO_SHOULD_I_BE],
+                        actual: devid,
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+    }
+
+    fn update_scale(&mut self) -> AccelerometerResult<()> {
+        let value = data_format_bits(&self.scale);
+
+        log::debug!(target: LOG_TARGET, "DATA_FORMAT (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::DATA_FORMAT, "DATA_FORMAT", value)
+    }
+
+    fn update_data_rate(&mut self) -> AccelerometerResult<()> {
+        // NOTE: LOW_POWER (bit 4) is left clear
+        let value = rate_bits(&self.data_rate);
+
+        log::debug!(
+            target: LOG_TARGET,
+            "BW_RATE (desired): {:#04x} ({:?})",
+            value,
+            self.data_rate
+        );
+
+        self.write_register(ChipConstants::BW_RATE, "BW_RATE", value)
+    }
+
+    fn standby(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::POWER_CTL,
+            "POWER_CTL",
+            ChipConstants::POWER_CTL_STANDBY_BITS,
+        )
+    }
+
+    fn measure(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::POWER_CTL,
+            "POWER_CTL",
+            ChipConstants::POWER_CTL_MEASURE_BITS,
+        )
+        .map(|_| {
+            // The first sample is available one sample period later
+            thread::sleep(self.data_rate.update_cycle_duration());
+        })
+    }
+
+    fn read_register(&self, register: u8, name: &str) -> AccelerometerResult<u8> {
+        super::read_register(&self.i2c, self.address, register, name)
+    }
+
+    fn write_register(&mut self, register: u8, name: &str, value: u8) -> AccelerometerResult<()> {
+        super::write_register(&self.i2c, self.address, register, name, value)
+    }
+}
+
+impl<I2C> AccelerometerChip for Adxl345Impl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    fn default_chip_address(&self) -> u8 {
+        ChipConstants::DEFAULT_I2C_ADDRESS
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::ADXL345
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        super::reconfigure(self, |chip| &mut chip.scale, scale, Self::update_scale)
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        super::reconfigure(
+            self,
+            |chip| &mut chip.data_rate,
+            data_rate,
+            Self::update_data_rate,
+        )
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.data_rate.update_cycle_duration()
+    }
+
+    fn raw_measurement(&self) -> AccelerometerResult<Value> {
+        // NOTE: Reading all six bytes in one transaction keeps the axes coherent
+        let mut data: [u8; 6] = [0; 6];
+
+        super::read_registers(
+            &self.i2c,
+            self.address,
+            ChipConstants::DATAX0,
+            "DATAX0",
+            &mut data,
+        )
+        .map(|_| Value {
+            x: to_meters_per_second(&data[0..2]),
+            y: to_meters_per_second(&data[2..4]),
+            z: to_meters_per_second(&data[4..6]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_format_bits, rate_bits, to_meters_per_second};
+    use crate::{utils, OutputDataRate, Scale};
+
+    #[test]
+    fn data_format_full_resolution() {
+        assert_eq!(data_format_bits(&Scale::TwoG), 0b00001000);
+        assert_eq!(data_format_bits(&Scale::SixteenG), 0b00001011);
+    }
+
+    #[test]
+    fn rate_codes() {
+        assert_eq!(rate_bits(&OutputDataRate::DataRate100Hz), 0x0a);
+        assert_eq!(rate_bits(&OutputDataRate::DataRate1_56Hz), 0x04);
+    }
+
+    #[test]
+    fn little_endian_conversion() {
+        // 256 LSB (3.9 mg/LSB) is just under 1 g
+        let value = to_meters_per_second(&[0x00, 0x01]);
+
+        assert!((value - 0.9984 * utils::G_METERS_PER_SECOND).abs() < 1e-9);
+        assert!(to_meters_per_second(&[0x00, 0xff]) < 0.0);
+    }
+}
@@ -0,0 +1,360 @@
+use std::{cell::RefCell, thread, time};
+
+use embedded_hal::blocking::i2c;
+use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+use crate::{
+    utils, AccelerometerError, AccelerometerResult, DeviceConfig, OutputDataRate, Scale,
+    SupportedChips, Value,
+};
+
+use super::{AccelerometerChip, ChipProbe};
+
+struct ChipConstants;
+impl ChipConstants {
+    // NOTE: The SDO pin selects 0x68 (low) or 0x69 (high)
+    const DEFAULT_I2C_ADDRESS: u8 = 0x68;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x69;
+
+    const CHIP_ID: u8 = 0x00;
+    const PMU_STATUS: u8 = 0x03;
+    const DATA_14: u8 = 0x12;
+    const ACC_CONF: u8 = 0x40;
+    const ACC_RANGE: u8 = 0x41;
+    const CMD: u8 = 0x7e;
+
+    const WHO_SHOULD_I_BE: u8 = 0xd1;
+
+    const CMD_SOFTRESET: u8 = 0xb6;
+    const CMD_ACC_SET_PMU_MODE_NORMAL: u8 = 0x11;
+
+    // Per the datasheet, the accelerometer needs up to 3.8 ms to start up
+    const SOFTRESET_DELAY: time::Duration = time::Duration::from_millis(50);
+    const ACC_STARTUP_DELAY: time::Duration = time::Duration::from_millis(4);
+
+    const PMU_STATUS_ACC_MASK: u8 = 0b00110000;
+    const PMU_STATUS_ACC_NORMAL_BITS: u8 = 0b00010000;
+
+    // acc_us clear (no undersampling) and acc_bwp = 0b010 (normal filter mode)
+    const ACC_CONF_NORMAL_BWP_BITS: u8 = 0b00100000;
+
+    const TWO_G_CFG_BITS: u8 = 0b0011;
+    const FOUR_G_CFG_BITS: u8 = 0b0101;
+    const EIGHT_G_CFG_BITS: u8 = 0b1000;
+    const SIXTEEN_G_CFG_BITS: u8 = 0b1100;
+
+    const TWO_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 16384.0;
+    const FOUR_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 8192.0;
+    const EIGHT_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 4096.0;
+    const SIXTEEN_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 2048.0;
+
+    const ODR_800HZ_BITS: u8 = 0x0b;
+    const ODR_400HZ_BITS: u8 = 0x0a;
+    const ODR_200HZ_BITS: u8 = 0x09;
+    const ODR_100HZ_BITS: u8 = 0x08;
+    const ODR_50HZ_BITS: u8 = 0x07;
+    const ODR_12_5HZ_BITS: u8 = 0x05;
+}
+
+const LOG_TARGET: &str = "bmi160";
+
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::BMI160,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::CHIP_ID,
+    identities: &[ChipConstants::WHO_SHOULD_I_BE],
+};
+
+pub(crate) struct Bmi160Impl<I2C> {
+    i2c: RefCell<I2C>,
+    address: u8,
+    scale: Scale,
+    data_rate: OutputDataRate,
+
+    /// The time between samples at the data rate actually programmed
+    sample_period: time::Duration,
+}
+
+/// The ACC_CONF ODR bits for `data_rate`, with the rate they select (in Hz)
+fn odr_bits(data_rate: &OutputDataRate) -> (u8, f64) {
+    // NOTE: Without undersampling (i.e, in normal mode), 12.5 Hz is the
+    //       lowest rate available
+    match data_rate {
+        OutputDataRate::DataRate800Hz => (ChipConstants::ODR_800HZ_BITS, 800.0),
+        OutputDataRate::DataRate400Hz => (ChipConstants::ODR_400HZ_BITS, 400.0),
+        OutputDataRate::DataRate200Hz => (ChipConstants::ODR_200HZ_BITS, 200.0),
+        OutputDataRate::DataRate100Hz => (ChipConstants::ODR_100HZ_BITS, 100.0),
+        OutputDataRate::DataRate50Hz => (ChipConstants::ODR_50HZ_BITS, 50.0),
+        OutputDataRate::DataRate12_5Hz
+        | OutputDataRate::DataRate6_25Hz
+        | OutputDataRate::DataRate1_56Hz => (ChipConstants::ODR_12_5HZ_BITS, 12.5),
+    }
+}
+
+fn range_bits(scale: &Scale) -> u8 {
+    match scale {
+        Scale::TwoG => ChipConstants::TWO_G_CFG_BITS,
+        Scale::FourG => ChipConstants::FOUR_G_CFG_BITS,
+        Scale::EightG => ChipConstants::EIGHT_G_CFG_BITS,
+        Scale::SixteenG => ChipConstants::SIXTEEN_G_CFG_BITS,
+    }
+}
+
+/// Checks PMU_STATUS reports the accelerometer in normal mode
+fn verify_acc_normal_mode(address: u8, pmu_status: u8) -> AccelerometerResult<()> {
+    let acc_pmu_status = pmu_status & ChipConstants::PMU_STATUS_ACC_MASK;
+
+    if acc_pmu_status != ChipConstants::PMU_STATUS_ACC_NORMAL_BITS {
+        Err(AccelerometerError::RegisterMismatch {
+            chip: SupportedChips::BMI160,
+            address,
+            register: "PMU_STATUS".to_string(),
+            expected: ChipConstants::PMU_STATUS_ACC_NORMAL_BITS,
+            actual: acc_pmu_status,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn to_meters_per_second(scale: &Scale, buffer: &[u8]) -> f64 {
+    let value = i16::from_le_bytes([buffer[0], buffer[1]]);
+
+    let factor = match scale {
+        Scale::TwoG => ChipConstants::TWO_G_SCALE_FACTOR,
+        Scale::FourG => ChipConstants::FOUR_G_SCALE_FACTOR,
+        Scale::EightG => ChipConstants::EIGHT_G_SCALE_FACTOR,
+        Scale::SixteenG => ChipConstants::SIXTEEN_G_SCALE_FACTOR,
+    };
+
+    factor * value as f64
+}
+
+impl<I2C> Bmi160Impl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
+        let mut chip = Bmi160Impl {
+            i2c: RefCell::new(i2c),
+            address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
+            scale: config.scale,
+            data_rate: config.data_rate,
+            sample_period: config.data_rate.update_cycle_duration(),
+        };
+
+        // NOTE: In suspend mode (after reset) writes need 450 µs between them,
+        //       so the accelerometer is powered up before it's configured
+        chip.verify_identity()
+            .and_then(|_| chip.reset())
+            .and_then(|_| chip.power_up())
+            .and_then(|_| chip.update_scale())
+            .and_then(|_| chip.update_data_rate())?;
+
+        Ok(chip)
+    }
+
+    fn verify_identity(&self) -> AccelerometerResult<()> {
+        self.read_register(ChipConstants::CHIP_ID, "CHIP_ID")
+            .and_then(|chip_id| {
+                if chip_id != ChipConstants::WHO_SHOULD_I_BE {
+                    Err(AccelerometerError::IdentityMismatch {
+                        chip: SupportedChips::BMI160,
+                        address: self.address,
+                        expected: &[ChipConstants::WHO_SHOULD_I_BE],
+                        actual: chip_id,
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+    }
+
+    fn reset(&mut self) -> AccelerometerResult<()> {
+        self.write_register(ChipConstants::CMD, "CMD", ChipConstants::CMD_SOFTRESET)
+            .map(|_| thread::sleep(ChipConstants::SOFTRESET_DELAY))
+    }
+
+    /// Moves the accelerometer from suspend (after reset) to normal mode
+    fn power_up(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::CMD,
+            "CMD",
+            ChipConstants::CMD_ACC_SET_PMU_MODE_NORMAL,
+        )
+        .map(|_| thread::sleep(ChipConstants::ACC_STARTUP_DELAY))
+        .and_then(|_| self.read_register(ChipConstants::PMU_STATUS, "PMU_STATUS"))
+        .and_then(|pmu_status| {
+            log::debug!(target: LOG_TARGET, "PMU_STATUS: {:#010b}", pmu_status);
+
+            verify_acc_normal_mode(self.address, pmu_status)
+        })
+    }
+
+    fn update_scale(&mut self) -> AccelerometerResult<()> {
+        let value = range_bits(&self.scale);
+
+        log::debug!(target: LOG_TARGET, "ACC_RANGE (desired): {:#010b}", value);
+
+        self.write_verified_register(ChipConstants::ACC_RANGE, "ACC_RANGE", value)
+    }
+
+    fn update_data_rate(&mut self) -> AccelerometerResult<()> {
+        let (odr, actual_rate_hz) = odr_bits(&self.data_rate);
+
+        if actual_rate_hz != self.data_rate.update_frequency_hz() {
+            log::info!(
+                target: LOG_TARGET,
+                "{:?} is not supported, using the nearest rate ({} Hz)",
+                self.data_rate,
+                actual_rate_hz
+            );
+        }
+
+        let value = ChipConstants::ACC_CONF_NORMAL_BWP_BITS | odr;
+
+        log::debug!(target: LOG_TARGET, "ACC_CONF (desired): {:#010b}", value);
+
+        self.write_verified_register(ChipConstants::ACC_CONF, "ACC_CONF", value)
+            .map(|_| {
+                self.sample_period = time::Duration::from_secs_f64(1.0 / actual_rate_hz);
+            })
+    }
+
+    fn read_register(&self, register: u8, name: &str) -> AccelerometerResult<u8> {
+        super::read_register(&self.i2c, self.address, register, name)
+    }
+
+    fn write_register(&mut self, register: u8, name: &str, value: u8) -> AccelerometerResult<()> {
+        super::write_register(&self.i2c, self.address, register, name, value)
+    }
+
+    /// Writes `value` to `register` and reads it back, as the chip silently
+    /// drops settings it can't apply (e.g, an invalid ODR for the power mode)
+    fn write_verified_register(
+        &mut self,
+        register: u8,
+        name: &str,
+        value: u8,
+    ) -> AccelerometerResult<()> {
+        self.write_register(register, name, value)
+            .and_then(|_| self.read_register(register, name))
+            .and_then(|actual| {
+                if actual != value {
+                    Err(AccelerometerError::RegisterMismatch {
+                        chip: SupportedChips::BMI160,
+                        address: self.address,
+                        register: name.to_string(),
+                        expected: value,
+                        actual,
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+    }
+}
+
+impl<I2C> AccelerometerChip for Bmi160Impl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    fn default_chip_address(&self) -> u8 {
+        ChipConstants::DEFAULT_I2C_ADDRESS
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::BMI160
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        super::reconfigure(self, |chip| &mut chip.scale, scale, Self::update_scale)
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        super::reconfigure(
+            self,
+            |chip| &mut chip.data_rate,
+            data_rate,
+            Self::update_data_rate,
+        )
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.sample_period
+    }
+
+    fn raw_measurement(&self) -> AccelerometerResult<Value> {
+        // NOTE: A burst read of DATA_14..DATA_19 is shadowed, keeping the axes coherent
+        let mut data: [u8; 6] = [0; 6];
+
+        super::read_registers(
+            &self.i2c,
+            self.address,
+            ChipConstants::DATA_14,
+            "DATA_14",
+            &mut data,
+        )
+        .map(|_| Value {
+            x: to_meters_per_second(&self.scale, &data[0..2]),
+            y: to_meters_per_second(&self.scale, &data[2..4]),
+            z: to_meters_per_second(&self.scale, &data[4..6]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{odr_bits, range_bits, to_meters_per_second, verify_acc_normal_mode};
+    use crate::{utils, AccelerometerError, OutputDataRate, Scale, SupportedChips};
+
+    #[test]
+    fn odr_falls_back_to_lowest_normal_mode_rate() {
+        assert_eq!(odr_bits(&OutputDataRate::DataRate800Hz), (0x0b, 800.0));
+        assert_eq!(odr_bits(&OutputDataRate::DataRate12_5Hz), (0x05, 12.5));
+        assert_eq!(odr_bits(&OutputDataRate::DataRate1_56Hz), (0x05, 12.5));
+    }
+
+    #[test]
+    fn acc_range_bits() {
+        assert_eq!(range_bits(&Scale::TwoG), 0x03);
+        assert_eq!(range_bits(&Scale::FourG), 0x05);
+        assert_eq!(range_bits(&Scale::EightG), 0x08);
+        assert_eq!(range_bits(&Scale::SixteenG), 0x0c);
+    }
+
+    #[test]
+    fn little_endian_conversion() {
+        assert_eq!(
+            to_meters_per_second(&Scale::TwoG, &[0x00, 0x40]),
+            utils::G_METERS_PER_SECOND
+        );
+        assert_eq!(
+            to_meters_per_second(&Scale::SixteenG, &[0x00, 0xf8]),
+            -utils::G_METERS_PER_SECOND
+        );
+    }
+
+    #[test]
+    fn acc_not_in_normal_mode() {
+        // Gyroscope and magnetometer bits are ignored
+        assert!(verify_acc_normal_mode(0x68, 0b00010101).is_ok());
+        assert!(matches!(
+            verify_acc_normal_mode(0x68, 0b00000000),
+            Err(AccelerometerError::RegisterMismatch {
+                chip: SupportedChips::BMI160,
+                address: 0x68,
+                expected: 0b00010000,
+                actual: 0b00000000,
+                ..
+            })
+        ));
+    }
+}
@@ -3,69 +3,257 @@ use std::{cell::RefCell, thread, time};
 use embedded_hal::blocking::i2c;
 use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
 
-use crate::{utils, AccelerometerError, AccelerometerResult, DeviceConfig, Scale, Value};
+use crate::{
+    AccelerometerError, AccelerometerResult, DeviceConfig, GyroScale, OutputDataRate, Scale,
+    SupportedChips, Value,
+};
 
-use super::AccelerometerChip;
+use super::{invensense, AccelerometerChip, ChipProbe};
 
 struct ChipConstants;
 impl ChipConstants {
     const DEFAULT_I2C_ADDRESS: u8 = 0x68;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x69;
 
     const WHO_AM_I: u8 = 0x00;
+    const USER_CTRL: u8 = 0x03;
+    const INT_PIN_CFG: u8 = 0x0f;
+    const INT_ENABLE_1: u8 = 0x11;
     const PWR_MGMT_1: u8 = 0x06;
     const _PWR_MGMT_2: u8 = 0x06;
+    const GYRO_SMPLRT_DIV: u8 = 0x00;
+    const GYRO_CONFIG_1: u8 = 0x01;
+    const ACCEL_SMPLRT_DIV_1: u8 = 0x10;
+    const ACCEL_SMPLRT_DIV_2: u8 = 0x11;
     const ACCEL_CFG: u8 = 0x14;
+    const I2C_MST_CTRL: u8 = 0x01;
+    const I2C_SLV0_ADDR: u8 = 0x03;
+    const I2C_SLV0_REG: u8 = 0x04;
+    const I2C_SLV0_CTRL: u8 = 0x05;
+    const I2C_SLV0_DO: u8 = 0x06;
     const ACCEL_XOUT_H: u8 = 0x2d;
+    const GYRO_XOUT_H: u8 = 0x33;
+    const TEMP_OUT_H: u8 = 0x39;
+    const EXT_SLV_SENS_DATA_00: u8 = 0x3b;
+    const FIFO_EN_2: u8 = 0x67;
+    const FIFO_RST: u8 = 0x68;
+    const FIFO_MODE: u8 = 0x69;
+    const FIFO_COUNTH: u8 = 0x70;
+    const FIFO_R_W: u8 = 0x72;
     const REG_BANK_SEL: u8 = 0x7f;
 
     const WHO_SHOULD_I_BE: u8 = 0xea;
     const USER_BANK_0: u8 = 0b00000000;
     const _USER_BANK_1: u8 = 0b00010000;
     const USER_BANK_2: u8 = 0b00100000;
-    const _USER_BANK_3: u8 = 0b00110000;
+    const USER_BANK_3: u8 = 0b00110000;
 
-    const TWO_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 16384.0;
-    const FOUR_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 8192.0;
-    const EIGHT_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 4096.0;
-    const SIXTEEN_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 2048.0;
+    // Per the datasheet: TEMP_degC = ((TEMP_OUT - RoomTemp_Offset) / Temp_Sensitivity) + 21
+    const TEMP_SENSITIVITY: f64 = 333.87;
+    const TEMP_ROOM_OFFSET: f64 = 0.0;
+    const TEMP_ROOM_DEGREES_C: f64 = 21.0;
 
     const PWR_MGMT_1_RESET_BITS: u8 = 0b10000000;
     const PWR_MGMT_1_ENABLE_BITS: u8 = 0b00000001;
+    const USER_CTRL_I2C_MST_EN_BITS: u8 = 0b00100000;
+    const USER_CTRL_FIFO_EN_BITS: u8 = 0b01000000;
+    const FIFO_EN_2_ACCEL_FIFO_EN_BITS: u8 = 0b00010000;
+    const FIFO_RST_ASSERT_BITS: u8 = 0b00011111;
+    const FIFO_MODE_STREAM_BITS: u8 = 0b00000000;
+    const FIFO_COUNT_MASK: u16 = 0x1fff;
+    const INT_PIN_CFG_ACTIVE_HIGH_PULSE_BITS: u8 = 0b00000000; // Push-pull, 50 µs pulse
+    const INT_ENABLE_1_RAW_DATA_0_RDY_EN_BITS: u8 = 0b00000001;
+    const I2C_MST_CLK_BITS: u8 = 0x07; // 345.6 kHz, as recommended by the datasheet
+    const I2C_SLV_EN_BITS: u8 = 0b10000000;
+    const I2C_SLV_READ_BITS: u8 = 0b10000000;
 
     const TWO_G_CFG_BITS: u8 = 0b00000000;
     const FOUR_G_CFG_BITS: u8 = 0b00000010;
     const EIGHT_G_CFG_BITS: u8 = 0b00000100;
     const SIXTEEN_G_CFG_BITS: u8 = 0b00000110;
-    const ACCEL_DLPCFG_BITS: u8 = 0b00110000; // Mode 6
     const ACCEL_FCHOICE_BITS: u8 = 0b00000001; // Enable digital low-pass filter (DLPF)
+
+    const DPS_250_CFG_BITS: u8 = 0b00000000;
+    const DPS_500_CFG_BITS: u8 = 0b00000010;
+    const DPS_1000_CFG_BITS: u8 = 0b00000100;
+    const DPS_2000_CFG_BITS: u8 = 0b00000110;
+    const GYRO_FCHOICE_BITS: u8 = 0b00000001; // Enable digital low-pass filter (DLPF)
+
+    /// ACCEL_DLPFCFG settings, as (3 dB bandwidth in Hz, ACCEL_DLPFCFG),
+    /// widest first
+    const ACCEL_DLPF_BANDWIDTHS: [(f64, u8); 7] = [
+        (473.0, 7),
+        (246.0, 1),
+        (111.4, 2),
+        (50.4, 3),
+        (23.9, 4),
+        (11.5, 5),
+        (5.7, 6),
+    ];
+
+    /// GYRO_DLPFCFG settings, as (3 dB bandwidth in Hz, GYRO_DLPFCFG), widest
+    /// first
+    const GYRO_DLPF_BANDWIDTHS: [(f64, u8); 8] = [
+        (361.4, 7),
+        (196.6, 0),
+        (151.8, 1),
+        (119.5, 2),
+        (51.2, 3),
+        (23.9, 4),
+        (11.6, 5),
+        (5.7, 6),
+    ];
+
+    // Per the datasheet: ODR = 1.125 kHz / (1 + ACCEL_SMPLRT_DIV[11:0])
+    const ACCEL_BASE_SAMPLE_RATE_HZ: f64 = 1125.0;
+    const MAX_ACCEL_SMPLRT_DIV: u16 = 0x0fff;
+
+    // Per the datasheet: ODR = 1.1 kHz / (1 + GYRO_SMPLRT_DIV[7:0])
+    const GYRO_BASE_SAMPLE_RATE_HZ: f64 = 1100.0;
+    const MAX_GYRO_SMPLRT_DIV: u8 = 0xff;
+}
+
+/// The AK09916 magnetometer, reachable only via the ICM-20948's auxiliary I²C master
+struct Ak09916Constants;
+impl Ak09916Constants {
+    const I2C_ADDRESS: u8 = 0x0c;
+
+    const WIA2: u8 = 0x01;
+    const HXL: u8 = 0x11;
+    const CNTL2: u8 = 0x31;
+    const CNTL3: u8 = 0x32;
+
+    const WHO_SHOULD_I_BE: u8 = 0x09;
+
+    /// HXL through ST2 (reading ST2 releases the data registers for the next sample)
+    const MEASUREMENT_LENGTH: u8 = 8;
+    const ST2_HOFL_BITS: u8 = 0b00001000;
+
+    const CNTL2_CONTINUOUS_100HZ_BITS: u8 = 0b00001000;
+    const CNTL3_SRST_BITS: u8 = 0b00000001;
+
+    const MICRO_TESLA_PER_LSB: f64 = 0.15;
 }
 
 const LOG_TARGET: &'static str = "icm20948";
 
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::ICM20948,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::WHO_AM_I,
+    identities: &[ChipConstants::WHO_SHOULD_I_BE],
+};
+
 pub(crate) struct Icm20948Impl<I2C> {
     i2c: RefCell<I2C>,
     address: u8,
     scale: Scale,
+    data_rate: OutputDataRate,
+    gyro_scale: GyroScale,
+    magnetometer: bool,
+    fifo: bool,
+
+    /// The time between samples at the data rate actually programmed
+    sample_period: time::Duration,
+
+    /// The most recently selected user bank (for error reporting)
+    bank: u8,
+}
+
+fn to_degrees_celsius(temp_out: i16) -> f64 {
+    (temp_out as f64 - ChipConstants::TEMP_ROOM_OFFSET) / ChipConstants::TEMP_SENSITIVITY
+        + ChipConstants::TEMP_ROOM_DEGREES_C
 }
 
-fn apply_scale(scale: &Scale, value: i16) -> f64 {
-    match scale {
-        Scale::TwoG => ChipConstants::TWO_G_SCALE_FACTOR * value as f64,
-        Scale::FourG => ChipConstants::FOUR_G_SCALE_FACTOR * value as f64,
-        Scale::EightG => ChipConstants::EIGHT_G_SCALE_FACTOR * value as f64,
-        Scale::SixteenG => ChipConstants::SIXTEEN_G_SCALE_FACTOR * value as f64,
+fn to_micro_tesla(buffer: &[u8]) -> f64 {
+    // NOTE: Unlike the ICM-20948 itself, the AK09916 is little-endian
+    let value = i16::from_le_bytes([buffer[0], buffer[1]]);
+
+    Ak09916Constants::MICRO_TESLA_PER_LSB * value as f64
+}
+
+/// Checks the AK09916's identity (WIA2) register value
+fn verify_magnetometer_wia2(wia2: u8) -> AccelerometerResult<()> {
+    if wia2 != Ak09916Constants::WHO_SHOULD_I_BE {
+        log::error!(
+            target: LOG_TARGET,
+            "AK09916 identity crisis!  It should be {:#04x}, but it's actually {:#04x}",
+            Ak09916Constants::WHO_SHOULD_I_BE,
+            wia2
+        );
+        Err(AccelerometerError::IdentityMismatch {
+            chip: SupportedChips::ICM20948,
+            address: Ak09916Constants::I2C_ADDRESS,
+            expected: &[Ak09916Constants::WHO_SHOULD_I_BE],
+            actual: wia2,
+        })
+    } else {
+        Ok(())
     }
 }
 
+/// The GYRO_SMPLRT_DIV whose output data rate is nearest to `data_rate`
+fn gyro_sample_rate_divider(data_rate: &OutputDataRate) -> u8 {
+    invensense::nearest_divider(
+        ChipConstants::GYRO_BASE_SAMPLE_RATE_HZ,
+        ChipConstants::MAX_GYRO_SMPLRT_DIV as u16,
+        data_rate,
+    ) as u8
+}
+
+/// The ACCEL_SMPLRT_DIV whose output data rate is nearest to `data_rate`
+fn sample_rate_divider(data_rate: &OutputDataRate) -> u16 {
+    invensense::nearest_divider(
+        ChipConstants::ACCEL_BASE_SAMPLE_RATE_HZ,
+        ChipConstants::MAX_ACCEL_SMPLRT_DIV,
+        data_rate,
+    )
+}
+
+/// The output data rate (in Hz) produced by an ACCEL_SMPLRT_DIV of `divider`
+fn divided_sample_rate_hz(divider: u16) -> f64 {
+    invensense::divided_rate_hz(ChipConstants::ACCEL_BASE_SAMPLE_RATE_HZ, divider)
+}
+
+/// The ACCEL_DLPFCFG whose bandwidth suits the output data rate programmed
+/// for `data_rate` (the widest below its Nyquist frequency)
+fn accel_dlpf_cfg(data_rate: &OutputDataRate) -> u8 {
+    invensense::dlpf_setting(
+        &ChipConstants::ACCEL_DLPF_BANDWIDTHS,
+        divided_sample_rate_hz(sample_rate_divider(data_rate)),
+    )
+}
+
+/// The GYRO_DLPFCFG whose bandwidth suits the output data rate programmed
+/// for `data_rate` (the widest below its Nyquist frequency)
+fn gyro_dlpf_cfg(data_rate: &OutputDataRate) -> u8 {
+    invensense::dlpf_setting(
+        &ChipConstants::GYRO_DLPF_BANDWIDTHS,
+        invensense::divided_rate_hz(
+            ChipConstants::GYRO_BASE_SAMPLE_RATE_HZ,
+            gyro_sample_rate_divider(data_rate).into(),
+        ),
+    )
+}
+
 impl<I2C> Icm20948Impl<I2C>
 where
     I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
 {
-    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> Self {
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
         let mut chip = Icm20948Impl {
             i2c: RefCell::new(i2c),
             address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
             scale: config.scale,
+            data_rate: config.data_rate,
+            gyro_scale: config.gyro_scale,
+            magnetometer: config.magnetometer,
+            fifo: config.fifo,
+            sample_period: config.data_rate.update_cycle_duration(),
+            bank: ChipConstants::USER_BANK_0,
         };
 
         chip.verify_identity()
@@ -76,12 +264,27 @@ where
             })
             .and_then(|_| chip.enable())
             .and_then(|_| chip.update_scale())
+            .and_then(|_| chip.update_data_rate())
+            .and_then(|_| chip.update_gyro_config())
+            .and_then(|_| {
+                if chip.magnetometer {
+                    chip.enable_magnetometer()
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|_| {
+                if chip.fifo {
+                    chip.enable_fifo()
+                } else {
+                    Ok(())
+                }
+            })
             //
             // Important! Default to USER_BANK_0 for subsequent reads of ACCEL_XOUT_H
-            .and_then(|_| chip.select_user_bank(ChipConstants::USER_BANK_0))
-            .unwrap();
+            .and_then(|_| chip.select_user_bank(ChipConstants::USER_BANK_0))?;
 
-        chip
+        Ok(chip)
     }
 
     fn verify_identity(&mut self) -> AccelerometerResult<()> {
@@ -95,7 +298,12 @@ where
                         ChipConstants::WHO_SHOULD_I_BE,
                         who_am_i
                     );
-                    Err(AccelerometerError::InvalidInputDataError)
+                    Err(AccelerometerError::IdentityMismatch {
+                        chip: SupportedChips::ICM20948,
+                        address: self.address,
+                        expected: &[ChipConstants::WHO_SHOULD_I_BE],
+                        actual: who_am_i,
+                    })
                 } else {
                     Ok(())
                 }
@@ -106,7 +314,7 @@ where
         log::debug!(target: LOG_TARGET, "REG_BANK_SEL: {}", bank >> 4);
 
         self.write_register(ChipConstants::REG_BANK_SEL, bank)
-            .and(Ok(()))
+            .map(|_| self.bank = bank)
     }
 
     fn reset(&mut self) -> AccelerometerResult<()> {
@@ -153,16 +361,21 @@ where
         self.i2c
             .borrow_mut()
             .write_read(self.address, &[register], &mut data)
-            .unwrap();
-
-        Ok(data[0])
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: self.describe("read", register),
+                source,
+            })
+            .and(Ok(data[0]))
     }
 
     fn write_register(&mut self, register: u8, value: u8) -> AccelerometerResult<u8> {
         let mut i2c = self.i2c.borrow_mut();
 
         i2c.write(self.address, &[register, value])
-            .map_err(AccelerometerError::I2CBusError)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: self.describe("write", register),
+                source,
+            })
             .and_then(|_| {
                 thread::sleep(time::Duration::from_secs_f64(0.1));
                 Ok(())
@@ -173,15 +386,17 @@ where
                 let mut data: [u8; 1] = [0];
 
                 i2c.write_read(self.address, &[register], &mut data)
-                    .map_err(AccelerometerError::I2CBusError)
+                    .map_err(|source| AccelerometerError::I2CBusError {
+                        operation: self.describe("read back", register),
+                        source,
+                    })
                     .and(Ok(data[0]))
             })
     }
 
     fn update_scale(&mut self) -> AccelerometerResult<()> {
-        // Implementation decision: This function name assumes we are only ever
-        // going to update the scale in ACCEL_CONFIG... we deliberately leave
-        // ACCEL_DLPFCFG and ACCEL_FCHOICE unchanged.
+        // NOTE: ACCEL_CONFIG also holds the DLPF, whose bandwidth follows the
+        //       data rate (so update_data_rate rewrites it)
 
         let scale_bits = match self.scale {
             Scale::TwoG => ChipConstants::TWO_G_CFG_BITS,
@@ -190,14 +405,15 @@ where
             Scale::SixteenG => ChipConstants::SIXTEEN_G_CFG_BITS,
         };
 
-        let value =
-            scale_bits | ChipConstants::ACCEL_DLPCFG_BITS | ChipConstants::ACCEL_FCHOICE_BITS;
+        let dlpf = accel_dlpf_cfg(&self.data_rate);
+        let value = scale_bits | dlpf << 3 | ChipConstants::ACCEL_FCHOICE_BITS;
 
         log::debug!(
             target: LOG_TARGET,
-            "ACCEL_CFG (desired): {:#04x} (scale: {})",
+            "ACCEL_CFG (desired): {:#04x} (scale: {}, DLPF: {})",
             value,
-            scale_bits >> 1
+            scale_bits >> 1,
+            dlpf
         );
 
         self.select_user_bank(ChipConstants::USER_BANK_2)
@@ -213,10 +429,199 @@ where
             })
     }
 
+    fn update_data_rate(&mut self) -> AccelerometerResult<()> {
+        // NOTE: The divider only takes effect while the DLPF is enabled
+        //       (ACCEL_FCHOICE; see update_scale)
+        let divider = sample_rate_divider(&self.data_rate);
+        let actual_rate_hz = divided_sample_rate_hz(divider);
+
+        log::debug!(
+            target: LOG_TARGET,
+            "ACCEL_SMPLRT_DIV (desired): {} ({:.2} Hz for {:?})",
+            divider,
+            actual_rate_hz,
+            self.data_rate
+        );
+
+        self.select_user_bank(ChipConstants::USER_BANK_2)
+            .and_then(|_| {
+                self.write_register(ChipConstants::ACCEL_SMPLRT_DIV_1, (divider >> 8) as u8)
+            })
+            .and_then(|_| {
+                self.write_register(ChipConstants::ACCEL_SMPLRT_DIV_2, (divider & 0xff) as u8)
+            })
+            .map(|_| {
+                self.sample_period = time::Duration::from_secs_f64(1.0 / actual_rate_hz);
+            })
+            // The DLPF's bandwidth follows the new rate
+            .and_then(|_| self.update_scale())
+    }
+
+    fn update_gyro_config(&mut self) -> AccelerometerResult<()> {
+        let scale_bits = match self.gyro_scale {
+            GyroScale::Dps250 => ChipConstants::DPS_250_CFG_BITS,
+            GyroScale::Dps500 => ChipConstants::DPS_500_CFG_BITS,
+            GyroScale::Dps1000 => ChipConstants::DPS_1000_CFG_BITS,
+            GyroScale::Dps2000 => ChipConstants::DPS_2000_CFG_BITS,
+        };
+
+        let dlpf = gyro_dlpf_cfg(&self.data_rate);
+        let value = scale_bits | dlpf << 3 | ChipConstants::GYRO_FCHOICE_BITS;
+        let divider = gyro_sample_rate_divider(&self.data_rate);
+
+        log::debug!(
+            target: LOG_TARGET,
+            "GYRO_CONFIG_1 (desired): {:#04x} (scale: {}, DLPF: {}), GYRO_SMPLRT_DIV: {} ({:.2} Hz)",
+            value,
+            scale_bits >> 1,
+            dlpf,
+            divider,
+            invensense::divided_rate_hz(ChipConstants::GYRO_BASE_SAMPLE_RATE_HZ, divider.into())
+        );
+
+        self.select_user_bank(ChipConstants::USER_BANK_2)
+            .and_then(|_| self.write_register(ChipConstants::GYRO_CONFIG_1, value))
+            .and_then(|_| self.write_register(ChipConstants::GYRO_SMPLRT_DIV, divider))
+            .map(|updated_value| {
+                log::debug!(
+                    target: LOG_TARGET,
+                    "Updated GYRO_SMPLRT_DIV value: {}",
+                    updated_value
+                );
+            })
+    }
+
+    /// Streams accelerometer samples (only) into the FIFO, discarding the
+    /// oldest once it is full
+    fn enable_fifo(&mut self) -> AccelerometerResult<()> {
+        self.select_user_bank(ChipConstants::USER_BANK_0)
+            .and_then(|_| self.read_register(ChipConstants::USER_CTRL))
+            .and_then(|user_ctrl| {
+                self.write_register(
+                    ChipConstants::USER_CTRL,
+                    user_ctrl | ChipConstants::USER_CTRL_FIFO_EN_BITS,
+                )
+            })
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::FIFO_MODE,
+                    ChipConstants::FIFO_MODE_STREAM_BITS,
+                )
+            })
+            .and_then(|_| {
+                self.write_register(ChipConstants::FIFO_RST, ChipConstants::FIFO_RST_ASSERT_BITS)
+            })
+            .and_then(|_| self.write_register(ChipConstants::FIFO_RST, 0))
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::FIFO_EN_2,
+                    ChipConstants::FIFO_EN_2_ACCEL_FIFO_EN_BITS,
+                )
+            })
+            .map(|_| ())
+    }
+
+    /// Configures the auxiliary I²C master to continuously copy the AK09916's
+    /// measurement into EXT_SLV_SENS_DATA_00..07
+    fn enable_magnetometer(&mut self) -> AccelerometerResult<()> {
+        self.select_user_bank(ChipConstants::USER_BANK_0)
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::USER_CTRL,
+                    ChipConstants::USER_CTRL_I2C_MST_EN_BITS,
+                )
+            })
+            .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_3))
+            .and_then(|_| {
+                self.write_register(ChipConstants::I2C_MST_CTRL, ChipConstants::I2C_MST_CLK_BITS)
+            })
+            .and_then(|_| {
+                self.write_magnetometer_register(
+                    Ak09916Constants::CNTL3,
+                    Ak09916Constants::CNTL3_SRST_BITS,
+                )
+            })
+            .and_then(|_| self.verify_magnetometer_identity())
+            .and_then(|_| {
+                self.write_magnetometer_register(
+                    Ak09916Constants::CNTL2,
+                    Ak09916Constants::CNTL2_CONTINUOUS_100HZ_BITS,
+                )
+            })
+            .and_then(|_| {
+                self.configure_slv0_read(
+                    Ak09916Constants::HXL,
+                    Ak09916Constants::MEASUREMENT_LENGTH,
+                )
+            })
+    }
+
+    /// Writes an AK09916 register via I2C_SLV0 (requires USER_BANK_3)
+    fn write_magnetometer_register(&mut self, register: u8, value: u8) -> AccelerometerResult<()> {
+        // Disable SLV0 while reprogramming it, so the I²C master never issues
+        // a write composed of stale and updated fields
+        self.write_register(ChipConstants::I2C_SLV0_CTRL, 0)
+            .and_then(|_| {
+                self.write_register(ChipConstants::I2C_SLV0_ADDR, Ak09916Constants::I2C_ADDRESS)
+            })
+            .and_then(|_| self.write_register(ChipConstants::I2C_SLV0_REG, register))
+            .and_then(|_| self.write_register(ChipConstants::I2C_SLV0_DO, value))
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::I2C_SLV0_CTRL,
+                    ChipConstants::I2C_SLV_EN_BITS | 1,
+                )
+            })
+            .and_then(|_| self.write_register(ChipConstants::I2C_SLV0_CTRL, 0))
+            .map(|_| ())
+    }
+
+    /// Configures I2C_SLV0 to read `length` AK09916 registers, starting at
+    /// `register`, into EXT_SLV_SENS_DATA_00 (requires USER_BANK_3)
+    fn configure_slv0_read(&mut self, register: u8, length: u8) -> AccelerometerResult<()> {
+        self.write_register(ChipConstants::I2C_SLV0_CTRL, 0)
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::I2C_SLV0_ADDR,
+                    ChipConstants::I2C_SLV_READ_BITS | Ak09916Constants::I2C_ADDRESS,
+                )
+            })
+            .and_then(|_| self.write_register(ChipConstants::I2C_SLV0_REG, register))
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::I2C_SLV0_CTRL,
+                    ChipConstants::I2C_SLV_EN_BITS | length,
+                )
+            })
+            .map(|_| ())
+    }
+
+    fn verify_magnetometer_identity(&mut self) -> AccelerometerResult<()> {
+        self.configure_slv0_read(Ak09916Constants::WIA2, 1)
+            .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_0))
+            .and_then(|_| self.read_register(ChipConstants::EXT_SLV_SENS_DATA_00))
+            .and_then(verify_magnetometer_wia2)
+            .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_3))
+    }
+
+    /// Describes a register access for error reporting, e.g.
+    /// "read register 0x2d (bank 0) on 0x68"
+    fn describe(&self, access: &str, register: u8) -> String {
+        format!(
+            "{} register {:#04x} (bank {}) on {:#04x}",
+            access,
+            register,
+            self.bank >> 4,
+            self.address
+        )
+    }
+
     fn to_meters_per_second(&self, buffer: &[u8]) -> f64 {
-        let value = i16::from_be_bytes([buffer[0], buffer[1]]);
+        invensense::to_meters_per_second(&self.scale, buffer)
+    }
 
-        apply_scale(&self.scale, value)
+    fn to_radians_per_second(&self, buffer: &[u8]) -> f64 {
+        invensense::to_radians_per_second(&self.gyro_scale, buffer)
     }
 }
 
@@ -228,16 +633,64 @@ where
         ChipConstants::DEFAULT_I2C_ADDRESS
     }
 
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::ICM20948
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        let previous = self.scale;
+        self.scale = scale;
+
+        self.update_scale()
+            .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_0))
+            .map_err(|e| {
+                // Best effort: write the previous scale back and return to
+                // bank 0, where measurements are read from
+                self.scale = previous;
+                let _ = self
+                    .update_scale()
+                    .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_0));
+                e
+            })
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        // The gyroscope's divider follows the accelerometer's data rate
+        let previous = self.data_rate;
+        self.data_rate = data_rate;
+
+        self.update_data_rate()
+            .and_then(|_| self.update_gyro_config())
+            .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_0))
+            .map_err(|e| {
+                // Best effort, as for set_scale
+                self.data_rate = previous;
+                let _ = self
+                    .update_data_rate()
+                    .and_then(|_| self.update_gyro_config())
+                    .and_then(|_| self.select_user_bank(ChipConstants::USER_BANK_0));
+                e
+            })
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.sample_period
+    }
+
     fn average(&self, sample_count: u8) -> AccelerometerResult<Value> {
         let mut avg: Value = Default::default();
 
         for c in 0..sample_count {
-            let m = self.raw_measurement().unwrap();
+            let m = self.raw_measurement()?;
 
             log::debug!(target: LOG_TARGET, "Zero sample {}: {:?}", c, m);
             avg.mut_add(&m);
 
-            thread::sleep(time::Duration::from_secs_f64(0.1));
+            thread::sleep(self.sample_period);
         }
 
         avg.mut_div(sample_count as f64);
@@ -251,11 +704,218 @@ where
         self.i2c
             .borrow_mut()
             .write_read(self.address, &[ChipConstants::ACCEL_XOUT_H], &mut data)
-            .map_err(AccelerometerError::I2CBusError)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read ACCEL_XOUT_H on {:#04x}", self.address),
+                source,
+            })
             .and(Ok(Value {
                 x: self.to_meters_per_second(&data[0..2]),
                 y: self.to_meters_per_second(&data[2..4]),
                 z: self.to_meters_per_second(&data[4..6]),
             }))
     }
+
+    fn angular_rate(&self) -> AccelerometerResult<Value> {
+        let mut data: [u8; 6] = [0; 6];
+
+        self.i2c
+            .borrow_mut()
+            .write_read(self.address, &[ChipConstants::GYRO_XOUT_H], &mut data)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read GYRO_XOUT_H on {:#04x}", self.address),
+                source,
+            })
+            .and(Ok(Value {
+                x: self.to_radians_per_second(&data[0..2]),
+                y: self.to_radians_per_second(&data[2..4]),
+                z: self.to_radians_per_second(&data[4..6]),
+            }))
+    }
+
+    /// Returns the magnetic field in the AK09916's own axes, which (per the
+    /// datasheet) differ in orientation from the accelerometer's Y and Z axes
+    fn magnetic_field(&self) -> AccelerometerResult<Value> {
+        if !self.magnetometer {
+            return Err(AccelerometerError::NotSupportedByChip {
+                chip: SupportedChips::ICM20948,
+                operation: "Magnetometer readout (disabled in configuration)".to_string(),
+            });
+        }
+
+        let mut data = [0; Ak09916Constants::MEASUREMENT_LENGTH as usize];
+
+        self.i2c
+            .borrow_mut()
+            .write_read(
+                self.address,
+                &[ChipConstants::EXT_SLV_SENS_DATA_00],
+                &mut data,
+            )
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read EXT_SLV_SENS_DATA_00 on {:#04x}", self.address),
+                source,
+            })?;
+
+        let st2 = data[Ak09916Constants::MEASUREMENT_LENGTH as usize - 1];
+        if st2 & Ak09916Constants::ST2_HOFL_BITS != 0 {
+            log::warn!(target: LOG_TARGET, "AK09916 magnetic sensor overflow");
+        }
+
+        Ok(Value {
+            x: to_micro_tesla(&data[0..2]),
+            y: to_micro_tesla(&data[2..4]),
+            z: to_micro_tesla(&data[4..6]),
+        })
+    }
+
+    fn temperature(&self) -> AccelerometerResult<f64> {
+        let mut data: [u8; 2] = [0; 2];
+
+        self.i2c
+            .borrow_mut()
+            .write_read(self.address, &[ChipConstants::TEMP_OUT_H], &mut data)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read TEMP_OUT_H on {:#04x}", self.address),
+                source,
+            })
+            .and(Ok(to_degrees_celsius(i16::from_be_bytes(data))))
+    }
+
+    fn drain_fifo(&self) -> AccelerometerResult<Vec<Value>> {
+        if !self.fifo {
+            return Err(AccelerometerError::NotSupportedByChip {
+                chip: SupportedChips::ICM20948,
+                operation: "FIFO readout (disabled in configuration)".to_string(),
+            });
+        }
+
+        let mut count: [u8; 2] = [0; 2];
+
+        self.i2c
+            .borrow_mut()
+            .write_read(self.address, &[ChipConstants::FIFO_COUNTH], &mut count)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read FIFO_COUNTH on {:#04x}", self.address),
+                source,
+            })?;
+
+        // Only read whole samples; a partial sample remains for the next drain
+        let count = u16::from_be_bytes(count) & ChipConstants::FIFO_COUNT_MASK;
+        let length = (count as usize / 6) * 6;
+        let mut data = vec![0; length];
+
+        if length > 0 {
+            self.i2c
+                .borrow_mut()
+                .write_read(self.address, &[ChipConstants::FIFO_R_W], &mut data)
+                .map_err(|source| AccelerometerError::I2CBusError {
+                    operation: format!(
+                        "read {} bytes of FIFO_R_W on {:#04x}",
+                        length, self.address
+                    ),
+                    source,
+                })?;
+        }
+
+        Ok(data
+            .chunks_exact(6)
+            .map(|sample| Value {
+                x: self.to_meters_per_second(&sample[0..2]),
+                y: self.to_meters_per_second(&sample[2..4]),
+                z: self.to_meters_per_second(&sample[4..6]),
+            })
+            .collect())
+    }
+
+    fn enable_data_ready_interrupt(&mut self) -> AccelerometerResult<()> {
+        self.select_user_bank(ChipConstants::USER_BANK_0)
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::INT_PIN_CFG,
+                    ChipConstants::INT_PIN_CFG_ACTIVE_HIGH_PULSE_BITS,
+                )
+            })
+            .and_then(|_| {
+                self.write_register(
+                    ChipConstants::INT_ENABLE_1,
+                    ChipConstants::INT_ENABLE_1_RAW_DATA_0_RDY_EN_BITS,
+                )
+            })
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accel_dlpf_cfg, divided_sample_rate_hz, gyro_dlpf_cfg, gyro_sample_rate_divider,
+        sample_rate_divider, to_degrees_celsius, to_micro_tesla, verify_magnetometer_wia2,
+    };
+    use crate::{AccelerometerError, OutputDataRate, SupportedChips};
+
+    #[test]
+    fn sample_rate_divider_nearest_rate() {
+        // 562.5 Hz is nearer to 800 Hz than 1125 Hz is
+        assert_eq!(sample_rate_divider(&OutputDataRate::DataRate800Hz), 1);
+        assert_eq!(sample_rate_divider(&OutputDataRate::DataRate400Hz), 2);
+        assert_eq!(sample_rate_divider(&OutputDataRate::DataRate100Hz), 10);
+        assert_eq!(sample_rate_divider(&OutputDataRate::DataRate50Hz), 22);
+        assert_eq!(sample_rate_divider(&OutputDataRate::DataRate1_56Hz), 720);
+    }
+
+    #[test]
+    fn divided_sample_rate() {
+        assert_eq!(divided_sample_rate_hz(0), 1125.0);
+        assert_eq!(divided_sample_rate_hz(10), 102.27272727272727);
+    }
+
+    #[test]
+    fn gyro_sample_rate_divider_saturates() {
+        assert_eq!(gyro_sample_rate_divider(&OutputDataRate::DataRate800Hz), 1);
+        assert_eq!(gyro_sample_rate_divider(&OutputDataRate::DataRate100Hz), 10);
+        assert_eq!(
+            gyro_sample_rate_divider(&OutputDataRate::DataRate1_56Hz),
+            0xff
+        );
+    }
+
+    #[test]
+    fn dlpf_follows_data_rate() {
+        // 562.5 Hz (for 800 Hz) keeps 246 Hz; 102.3 Hz (for 100 Hz) 50.4 Hz
+        assert_eq!(accel_dlpf_cfg(&OutputDataRate::DataRate800Hz), 1);
+        assert_eq!(accel_dlpf_cfg(&OutputDataRate::DataRate400Hz), 2);
+        assert_eq!(accel_dlpf_cfg(&OutputDataRate::DataRate100Hz), 3);
+        assert_eq!(accel_dlpf_cfg(&OutputDataRate::DataRate1_56Hz), 6);
+
+        // 550 Hz (for 800 Hz) keeps 196.6 Hz
+        assert_eq!(gyro_dlpf_cfg(&OutputDataRate::DataRate800Hz), 0);
+        assert_eq!(gyro_dlpf_cfg(&OutputDataRate::DataRate1_56Hz), 6);
+    }
+
+    #[test]
+    fn temperature_degrees_celsius() {
+        assert_eq!(to_degrees_celsius(0), 21.0);
+        assert!((to_degrees_celsius(3339) - 31.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn magnetometer_micro_tesla() {
+        // Little-endian, at 0.15 µT/LSB
+        assert_eq!(to_micro_tesla(&[0x64, 0x00]), 15.0);
+        assert_eq!(to_micro_tesla(&[0x9c, 0xff]), -15.0);
+    }
+
+    #[test]
+    fn magnetometer_identity_mismatch() {
+        assert!(verify_magnetometer_wia2(0x09).is_ok());
+        assert!(matches!(
+            verify_magnetometer_wia2(0x48),
+            Err(AccelerometerError::IdentityMismatch {
+                chip: SupportedChips::ICM20948,
+                address: 0x0c,
+                expected: &[0x09],
+                actual: 0x48,
+            })
+        ));
+    }
 }
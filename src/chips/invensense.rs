@@ -0,0 +1,154 @@
+//! Sample conversions shared by InvenSense chips (the ICM-20948 and the
+//! MPU-6050/9250), whose accelerometer and gyroscope report big-endian
+//! 16-bit samples with the same sensitivities, and whose output data rates
+//! are set by dividing a base sample rate
+
+use crate::{utils, GyroScale, OutputDataRate, Scale};
+
+struct Sensitivity;
+impl Sensitivity {
+    const TWO_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 16384.0;
+    const FOUR_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 8192.0;
+    const EIGHT_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 4096.0;
+    const SIXTEEN_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 2048.0;
+
+    // In LSB/(°/s)
+    const DPS_250_SENSITIVITY: f64 = 131.0;
+    const DPS_500_SENSITIVITY: f64 = 65.5;
+    const DPS_1000_SENSITIVITY: f64 = 32.8;
+    const DPS_2000_SENSITIVITY: f64 = 16.4;
+}
+
+fn apply_scale(scale: &Scale, value: i16) -> f64 {
+    match scale {
+        Scale::TwoG => Sensitivity::TWO_G_SCALE_FACTOR * value as f64,
+        Scale::FourG => Sensitivity::FOUR_G_SCALE_FACTOR * value as f64,
+        Scale::EightG => Sensitivity::EIGHT_G_SCALE_FACTOR * value as f64,
+        Scale::SixteenG => Sensitivity::SIXTEEN_G_SCALE_FACTOR * value as f64,
+    }
+}
+
+fn apply_gyro_scale(scale: &GyroScale, value: i16) -> f64 {
+    let degrees_per_second = match scale {
+        GyroScale::Dps250 => value as f64 / Sensitivity::DPS_250_SENSITIVITY,
+        GyroScale::Dps500 => value as f64 / Sensitivity::DPS_500_SENSITIVITY,
+        GyroScale::Dps1000 => value as f64 / Sensitivity::DPS_1000_SENSITIVITY,
+        GyroScale::Dps2000 => value as f64 / Sensitivity::DPS_2000_SENSITIVITY,
+    };
+
+    degrees_per_second.to_radians()
+}
+
+/// Converts a big-endian accelerometer sample (the first two bytes of
+/// `buffer`) to m/s²
+pub(crate) fn to_meters_per_second(scale: &Scale, buffer: &[u8]) -> f64 {
+    apply_scale(scale, i16::from_be_bytes([buffer[0], buffer[1]]))
+}
+
+/// Converts a big-endian gyroscope sample (the first two bytes of `buffer`)
+/// to rad/s
+pub(crate) fn to_radians_per_second(scale: &GyroScale, buffer: &[u8]) -> f64 {
+    apply_gyro_scale(scale, i16::from_be_bytes([buffer[0], buffer[1]]))
+}
+
+/// The divider (of `base_rate_hz`, at most `max_divider`) whose output data
+/// rate, `base_rate_hz / (1 + divider)`, is nearest to `data_rate`
+pub(crate) fn nearest_divider(
+    base_rate_hz: f64,
+    max_divider: u16,
+    data_rate: &OutputDataRate,
+) -> u16 {
+    let desired_hz = data_rate.update_frequency_hz();
+    let divider = (base_rate_hz / desired_hz - 1.0).clamp(0.0, max_divider as f64);
+    let (floor, ceil) = (divider.floor() as u16, divider.ceil() as u16);
+    let rate_error = |divider: u16| (divided_rate_hz(base_rate_hz, divider) - desired_hz).abs();
+
+    // NOTE: The rate is inversely proportional to the divider, so rounding
+    //       the divider itself doesn't necessarily give the nearest rate
+    if rate_error(floor) <= rate_error(ceil) {
+        floor
+    } else {
+        ceil
+    }
+}
+
+/// The output data rate (in Hz) produced by dividing `base_rate_hz` by
+/// `divider`
+pub(crate) fn divided_rate_hz(base_rate_hz: f64, divider: u16) -> f64 {
+    base_rate_hz / (1.0 + divider as f64)
+}
+
+/// The low-pass filter setting, from `bandwidths` (as (bandwidth in Hz,
+/// setting), widest first), with the widest bandwidth below the Nyquist
+/// frequency of `sample_rate_hz` (or the narrowest, if none is)
+pub(crate) fn dlpf_setting(bandwidths: &[(f64, u8)], sample_rate_hz: f64) -> u8 {
+    bandwidths
+        .iter()
+        .find(|(bandwidth_hz, _)| *bandwidth_hz <= sample_rate_hz / 2.0)
+        .unwrap_or(&bandwidths[bandwidths.len() - 1])
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_gyro_scale, divided_rate_hz, dlpf_setting, nearest_divider, to_meters_per_second,
+    };
+    use crate::{utils, GyroScale, OutputDataRate, Scale};
+
+    #[test]
+    fn big_endian_meters_per_second() {
+        assert_eq!(
+            to_meters_per_second(&Scale::TwoG, &[0x40, 0x00]),
+            utils::G_METERS_PER_SECOND
+        );
+        assert_eq!(
+            to_meters_per_second(&Scale::SixteenG, &[0xf8, 0x00]),
+            -utils::G_METERS_PER_SECOND
+        );
+    }
+
+    #[test]
+    fn gyro_scale_radians_per_second() {
+        assert_eq!(
+            apply_gyro_scale(&GyroScale::Dps250, 131),
+            1.0f64.to_radians()
+        );
+        assert_eq!(
+            apply_gyro_scale(&GyroScale::Dps2000, -164),
+            -10.0f64.to_radians()
+        );
+    }
+
+    #[test]
+    fn divider_with_nearest_rate() {
+        // 1000 Hz is nearer to 800 Hz than 500 Hz is, though 1000 / 800 - 1
+        // rounds to 0 either way; at 1125 Hz, 562.5 Hz is nearer than 1125 Hz
+        assert_eq!(
+            nearest_divider(1000.0, 0xff, &OutputDataRate::DataRate800Hz),
+            0
+        );
+        assert_eq!(
+            nearest_divider(1125.0, 0xfff, &OutputDataRate::DataRate800Hz),
+            1
+        );
+        assert_eq!(
+            nearest_divider(1000.0, 0xff, &OutputDataRate::DataRate100Hz),
+            9
+        );
+        assert_eq!(
+            nearest_divider(1000.0, 0xff, &OutputDataRate::DataRate1_56Hz),
+            0xff
+        );
+        assert_eq!(divided_rate_hz(1000.0, 9), 100.0);
+    }
+
+    #[test]
+    fn dlpf_below_nyquist() {
+        let bandwidths = [(100.0, 1), (40.0, 2), (10.0, 3)];
+
+        assert_eq!(dlpf_setting(&bandwidths, 1000.0), 1);
+        assert_eq!(dlpf_setting(&bandwidths, 80.0), 2);
+        assert_eq!(dlpf_setting(&bandwidths, 5.0), 3);
+    }
+}
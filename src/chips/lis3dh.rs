@@ -0,0 +1,282 @@
+use std::{cell::RefCell, thread, time};
+
+use embedded_hal::blocking::i2c;
+use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+use crate::{
+    utils, AccelerometerError, AccelerometerResult, DeviceConfig, OutputDataRate, Scale,
+    SupportedChips, Value,
+};
+
+use super::{AccelerometerChip, ChipProbe};
+
+struct ChipConstants;
+impl ChipConstants {
+    // NOTE: The SA0 pin selects 0x18 (low) or 0x19 (high)
+    const DEFAULT_I2C_ADDRESS: u8 = 0x18;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x19;
+
+    const WHO_AM_I: u8 = 0x0f;
+    const CTRL_REG1: u8 = 0x20;
+    const CTRL_REG4: u8 = 0x23;
+    const OUT_X_L: u8 = 0x28;
+
+    // Shared by the LIS3DH and the LIS2DH12
+    const WHO_SHOULD_I_BE: u8 = 0x33;
+
+    // Set in the register address to read consecutive registers in one transaction
+    const AUTO_INCREMENT_BIT: u8 = 0b10000000;
+
+    // Enables the X, Y and Z axes (with LPen clear, i.e, not in low-power mode)
+    const CTRL_REG1_XYZ_ENABLE_BITS: u8 = 0b00000111;
+
+    const CTRL_REG4_BDU_BITS: u8 = 0b10000000;
+    const CTRL_REG4_HR_BITS: u8 = 0b00001000;
+
+    const TWO_G_CFG_BITS: u8 = 0b00000000;
+    const FOUR_G_CFG_BITS: u8 = 0b00010000;
+    const EIGHT_G_CFG_BITS: u8 = 0b00100000;
+    const SIXTEEN_G_CFG_BITS: u8 = 0b00110000;
+
+    // High-resolution sensitivity, in g per (12-bit) LSB
+    const TWO_G_SCALE_FACTOR: f64 = 0.001;
+    const FOUR_G_SCALE_FACTOR: f64 = 0.002;
+    const EIGHT_G_SCALE_FACTOR: f64 = 0.004;
+    const SIXTEEN_G_SCALE_FACTOR: f64 = 0.012;
+
+    /// Output data rates available in normal/high-resolution mode, in Hz,
+    /// with their CTRL_REG1 ODR bits
+    const DATA_RATES: [(f64, u8); 8] = [
+        (1.0, 0b0001_0000),
+        (10.0, 0b0010_0000),
+        (25.0, 0b0011_0000),
+        (50.0, 0b0100_0000),
+        (100.0, 0b0101_0000),
+        (200.0, 0b0110_0000),
+        (400.0, 0b0111_0000),
+        (1344.0, 0b1001_0000),
+    ];
+}
+
+const LOG_TARGET: &str = "lis3dh";
+
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::LIS3DH,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::WHO_AM_I,
+    identities: &[ChipConstants::WHO_SHOULD_I_BE],
+};
+
+pub(crate) struct Lis3dhImpl<I2C> {
+    i2c: RefCell<I2C>,
+    address: u8,
+    scale: Scale,
+    data_rate: OutputDataRate,
+
+    /// The period of the chip's (nearest supported) output data rate
+    sample_period: time::Duration,
+}
+
+/// The supported output data rate nearest to `data_rate`, as (Hz, ODR bits)
+fn nearest_data_rate(data_rate: &OutputDataRate) -> (f64, u8) {
+    super::nearest_rate(&ChipConstants::DATA_RATES, data_rate)
+}
+
+/// The CTRL_REG4 value for `scale`, in high-resolution mode
+fn ctrl_reg4_bits(scale: &Scale) -> u8 {
+    let scale_bits = match scale {
+        Scale::TwoG => ChipConstants::TWO_G_CFG_BITS,
+        Scale::FourG => ChipConstants::FOUR_G_CFG_BITS,
+        Scale::EightG => ChipConstants::EIGHT_G_CFG_BITS,
+        Scale::SixteenG => ChipConstants::SIXTEEN_G_CFG_BITS,
+    };
+
+    ChipConstants::CTRL_REG4_BDU_BITS | ChipConstants::CTRL_REG4_HR_BITS | scale_bits
+}
+
+fn to_meters_per_second(scale: &Scale, buffer: &[u8]) -> f64 {
+    // NOTE: High-resolution samples are 12 bits, left-justified
+    let value = i16::from_le_bytes([buffer[0], buffer[1]]) >> 4;
+
+    let factor = match scale {
+        Scale::TwoG => ChipConstants::TWO_G_SCALE_FACTOR,
+        Scale::FourG => ChipConstants::FOUR_G_SCALE_FACTOR,
+        Scale::EightG => ChipConstants::EIGHT_G_SCALE_FACTOR,
+        Scale::SixteenG => ChipConstants::SIXTEEN_G_SCALE_FACTOR,
+    };
+
+    utils::G_METERS_PER_SECOND * factor * value as f64
+}
+
+impl<I2C> Lis3dhImpl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
+        let mut chip = Lis3dhImpl {
+            i2c: RefCell::new(i2c),
+            address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
+            scale: config.scale,
+            data_rate: config.data_rate,
+            sample_period: config.data_rate.update_cycle_duration(),
+        };
+
+        chip.verify_identity()
+            .and_then(|_| chip.update_scale())
+            .and_then(|_| chip.update_data_rate())?;
+
+        Ok(chip)
+    }
+
+    fn verify_identity(&self) -> AccelerometerResult<()> {
+        self.read_register(ChipConstants::WHO_AM_I, "WHO_AM_I")
+            .and_then(|who_am_i| {
+                if who_am_i != ChipConstants::WHO_SHOULD_I_BE {
+                    Err(AccelerometerError::IdentityMismatch {
+                        chip: SupportedChips::LIS3DH,
+                        address: self.address,
+                        expected: &[ChipConstants::WHO_SHOULD_I_BE],
+                        actual: who_am_i,
+                    })
+                } else {
+                    Ok(())
+                }
+            })
+    }
+
+    fn update_scale(&mut self) -> AccelerometerResult<()> {
+        let value = ctrl_reg4_bits(&self.scale);
+
+        log::debug!(target: LOG_TARGET, "CTRL_REG4 (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::CTRL_REG4, "CTRL_REG4", value)
+    }
+
+    fn update_data_rate(&mut self) -> AccelerometerResult<()> {
+        let (actual_rate_hz, odr_bits) = nearest_data_rate(&self.data_rate);
+
+        if actual_rate_hz != self.data_rate.update_frequency_hz() {
+            log::info!(
+                target: LOG_TARGET,
+                "{:?} is not supported, using the nearest rate ({} Hz)",
+                self.data_rate,
+                actual_rate_hz
+            );
+        }
+
+        let value = odr_bits | ChipConstants::CTRL_REG1_XYZ_ENABLE_BITS;
+
+        log::debug!(target: LOG_TARGET, "CTRL_REG1 (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::CTRL_REG1, "CTRL_REG1", value)
+            .map(|_| {
+                self.sample_period = time::Duration::from_secs_f64(1.0 / actual_rate_hz);
+
+                // The first sample at the new rate is available one sample period later
+                thread::sleep(self.sample_period);
+            })
+    }
+
+    fn read_register(&self, register: u8, name: &str) -> AccelerometerResult<u8> {
+        super::read_register(&self.i2c, self.address, register, name)
+    }
+
+    fn write_register(&mut self, register: u8, name: &str, value: u8) -> AccelerometerResult<()> {
+        super::write_register(&self.i2c, self.address, register, name, value)
+    }
+}
+
+impl<I2C> AccelerometerChip for Lis3dhImpl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    fn default_chip_address(&self) -> u8 {
+        ChipConstants::DEFAULT_I2C_ADDRESS
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::LIS3DH
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        super::reconfigure(self, |chip| &mut chip.scale, scale, Self::update_scale)
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        super::reconfigure(
+            self,
+            |chip| &mut chip.data_rate,
+            data_rate,
+            Self::update_data_rate,
+        )
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.sample_period
+    }
+
+    fn raw_measurement(&self) -> AccelerometerResult<Value> {
+        let mut data: [u8; 6] = [0; 6];
+
+        super::read_registers(
+            &self.i2c,
+            self.address,
+            ChipConstants::OUT_X_L | ChipConstants::AUTO_INCREMENT_BIT,
+            "OUT_X_L",
+            &mut data,
+        )
+        .map(|_| Value {
+            x: to_meters_per_second(&self.scale, &data[0..2]),
+            y: to_meters_per_second(&self.scale, &data[2..4]),
+            z: to_meters_per_second(&self.scale, &data[4..6]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ctrl_reg4_bits, nearest_data_rate, to_meters_per_second};
+    use crate::{utils, OutputDataRate, Scale};
+
+    #[test]
+    fn nearest_supported_data_rate() {
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate100Hz),
+            (100.0, 0b0101_0000)
+        );
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate800Hz),
+            (400.0, 0b0111_0000)
+        );
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate12_5Hz),
+            (10.0, 0b0010_0000)
+        );
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate1_56Hz),
+            (1.0, 0b0001_0000)
+        );
+    }
+
+    #[test]
+    fn ctrl_reg4_high_resolution() {
+        assert_eq!(ctrl_reg4_bits(&Scale::TwoG), 0b10001000);
+        assert_eq!(ctrl_reg4_bits(&Scale::SixteenG), 0b10111000);
+    }
+
+    #[test]
+    fn left_justified_conversion() {
+        // 1000 (12-bit) LSB at 1 mg/LSB is 1 g
+        let value = to_meters_per_second(&Scale::TwoG, &(1000i16 << 4).to_le_bytes());
+
+        assert!((value - utils::G_METERS_PER_SECOND).abs() < 1e-9);
+        assert!(to_meters_per_second(&Scale::TwoG, &(-1000i16 << 4).to_le_bytes()) < 0.0);
+    }
+}
@@ -0,0 +1,344 @@
+use std::{cell::RefCell, thread, time};
+
+use embedded_hal::blocking::i2c;
+use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+use crate::{
+    utils, AccelerometerError, AccelerometerResult, DeviceConfig, GyroScale, OutputDataRate, Scale,
+    SupportedChips, Value,
+};
+
+use super::{AccelerometerChip, ChipProbe};
+
+struct ChipConstants;
+impl ChipConstants {
+    // NOTE: The SA0 pin selects 0x6a (low) or 0x6b (high)
+    const DEFAULT_I2C_ADDRESS: u8 = 0x6a;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x6b;
+
+    const WHO_AM_I: u8 = 0x0f;
+    const CTRL1_XL: u8 = 0x10;
+    const CTRL2_G: u8 = 0x11;
+    const CTRL3_C: u8 = 0x12;
+    const OUTX_L_G: u8 = 0x22;
+    const OUTX_L_A: u8 = 0x28;
+
+    const LSM6DSOX_WHO_AM_I: u8 = 0x6c;
+    const LSM6DS3_WHO_AM_I: u8 = 0x69;
+    const LSM6DS3TR_C_WHO_AM_I: u8 = 0x6a;
+
+    const CTRL3_C_SW_RESET_BITS: u8 = 0b00000001;
+    // BDU (outputs aren't updated until both bytes are read) and IF_INC
+    // (auto-increment for multi-byte reads)
+    const CTRL3_C_BDU_IF_INC_BITS: u8 = 0b01000100;
+    const SW_RESET_DELAY: time::Duration = time::Duration::from_millis(10);
+
+    const TWO_G_CFG_BITS: u8 = 0b00000000;
+    const FOUR_G_CFG_BITS: u8 = 0b00001000;
+    const EIGHT_G_CFG_BITS: u8 = 0b00001100;
+    const SIXTEEN_G_CFG_BITS: u8 = 0b00000100;
+
+    const DPS_250_CFG_BITS: u8 = 0b00000000;
+    const DPS_500_CFG_BITS: u8 = 0b00000100;
+    const DPS_1000_CFG_BITS: u8 = 0b00001000;
+    const DPS_2000_CFG_BITS: u8 = 0b00001100;
+
+    // Sensitivity, in g/LSB
+    const TWO_G_SCALE_FACTOR: f64 = 0.000061;
+    const FOUR_G_SCALE_FACTOR: f64 = 0.000122;
+    const EIGHT_G_SCALE_FACTOR: f64 = 0.000244;
+    const SIXTEEN_G_SCALE_FACTOR: f64 = 0.000488;
+
+    // Sensitivity, in (°/s)/LSB
+    const DPS_250_SCALE_FACTOR: f64 = 0.00875;
+    const DPS_500_SCALE_FACTOR: f64 = 0.0175;
+    const DPS_1000_SCALE_FACTOR: f64 = 0.035;
+    const DPS_2000_SCALE_FACTOR: f64 = 0.07;
+
+    /// Output data rates shared by the accelerometer and gyroscope (in
+    /// high-performance mode), in Hz, with their ODR bits
+    const DATA_RATES: [(f64, u8); 7] = [
+        (12.5, 0b0001_0000),
+        (26.0, 0b0010_0000),
+        (52.0, 0b0011_0000),
+        (104.0, 0b0100_0000),
+        (208.0, 0b0101_0000),
+        (416.0, 0b0110_0000),
+        (833.0, 0b0111_0000),
+    ];
+}
+
+const LOG_TARGET: &str = "lsm6dsox";
+
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::LSM6DSOX,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::WHO_AM_I,
+    identities: &[
+        ChipConstants::LSM6DSOX_WHO_AM_I,
+        ChipConstants::LSM6DS3_WHO_AM_I,
+        ChipConstants::LSM6DS3TR_C_WHO_AM_I,
+    ],
+};
+
+pub(crate) struct Lsm6dsoxImpl<I2C> {
+    i2c: RefCell<I2C>,
+    address: u8,
+    scale: Scale,
+    data_rate: OutputDataRate,
+    gyro_scale: GyroScale,
+
+    /// The time between samples at the data rate actually programmed
+    sample_period: time::Duration,
+}
+
+/// The supported output data rate nearest to `data_rate`, as (Hz, ODR bits)
+fn nearest_data_rate(data_rate: &OutputDataRate) -> (f64, u8) {
+    super::nearest_rate(&ChipConstants::DATA_RATES, data_rate)
+}
+
+fn to_meters_per_second(scale: &Scale, buffer: &[u8]) -> f64 {
+    let value = i16::from_le_bytes([buffer[0], buffer[1]]);
+
+    let factor = match scale {
+        Scale::TwoG => ChipConstants::TWO_G_SCALE_FACTOR,
+        Scale::FourG => ChipConstants::FOUR_G_SCALE_FACTOR,
+        Scale::EightG => ChipConstants::EIGHT_G_SCALE_FACTOR,
+        Scale::SixteenG => ChipConstants::SIXTEEN_G_SCALE_FACTOR,
+    };
+
+    utils::G_METERS_PER_SECOND * factor * value as f64
+}
+
+fn to_radians_per_second(scale: &GyroScale, buffer: &[u8]) -> f64 {
+    let value = i16::from_le_bytes([buffer[0], buffer[1]]);
+
+    let factor = match scale {
+        GyroScale::Dps250 => ChipConstants::DPS_250_SCALE_FACTOR,
+        GyroScale::Dps500 => ChipConstants::DPS_500_SCALE_FACTOR,
+        GyroScale::Dps1000 => ChipConstants::DPS_1000_SCALE_FACTOR,
+        GyroScale::Dps2000 => ChipConstants::DPS_2000_SCALE_FACTOR,
+    };
+
+    (factor * value as f64).to_radians()
+}
+
+impl<I2C> Lsm6dsoxImpl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
+        let mut chip = Lsm6dsoxImpl {
+            i2c: RefCell::new(i2c),
+            address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
+            scale: config.scale,
+            data_rate: config.data_rate,
+            gyro_scale: config.gyro_scale,
+            sample_period: config.data_rate.update_cycle_duration(),
+        };
+
+        chip.verify_identity()
+            .and_then(|_| chip.reset())
+            .and_then(|_| chip.enable_block_data_update())
+            .and_then(|_| chip.update_data_rate())?;
+
+        Ok(chip)
+    }
+
+    fn verify_identity(&self) -> AccelerometerResult<()> {
+        self.read_register(ChipConstants::WHO_AM_I, "WHO_AM_I")
+            .and_then(|who_am_i| match who_am_i {
+                ChipConstants::LSM6DSOX_WHO_AM_I
+                | ChipConstants::LSM6DS3_WHO_AM_I
+                | ChipConstants::LSM6DS3TR_C_WHO_AM_I => {
+                    log::debug!(target: LOG_TARGET, "WHO_AM_I: {:#04x}", who_am_i);
+                    Ok(())
+                }
+                _ => Err(AccelerometerError::IdentityMismatch {
+                    chip: SupportedChips::LSM6DSOX,
+                    address: self.address,
+                    expected: PROBE.identities,
+                    actual: who_am_i,
+                }),
+            })
+    }
+
+    fn reset(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::CTRL3_C,
+            "CTRL3_C",
+            ChipConstants::CTRL3_C_SW_RESET_BITS,
+        )
+        .map(|_| thread::sleep(ChipConstants::SW_RESET_DELAY))
+    }
+
+    fn enable_block_data_update(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::CTRL3_C,
+            "CTRL3_C",
+            ChipConstants::CTRL3_C_BDU_IF_INC_BITS,
+        )
+    }
+
+    /// Writes CTRL1_XL, which holds both the scale and the data rate
+    fn update_accel_config(&mut self) -> AccelerometerResult<()> {
+        let (actual_rate_hz, odr_bits) = nearest_data_rate(&self.data_rate);
+
+        if actual_rate_hz != self.data_rate.update_frequency_hz() {
+            log::info!(
+                target: LOG_TARGET,
+                "{:?} is not supported, using the nearest rate ({} Hz)",
+                self.data_rate,
+                actual_rate_hz
+            );
+        }
+
+        let scale_bits = match self.scale {
+            Scale::TwoG => ChipConstants::TWO_G_CFG_BITS,
+            Scale::FourG => ChipConstants::FOUR_G_CFG_BITS,
+            Scale::EightG => ChipConstants::EIGHT_G_CFG_BITS,
+            Scale::SixteenG => ChipConstants::SIXTEEN_G_CFG_BITS,
+        };
+        let value = odr_bits | scale_bits;
+
+        log::debug!(target: LOG_TARGET, "CTRL1_XL (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::CTRL1_XL, "CTRL1_XL", value)
+            .map(|_| {
+                self.sample_period = time::Duration::from_secs_f64(1.0 / actual_rate_hz);
+            })
+    }
+
+    /// Writes CTRL2_G (the gyroscope's data rate follows the accelerometer's)
+    fn update_gyro_config(&mut self) -> AccelerometerResult<()> {
+        let (_, odr_bits) = nearest_data_rate(&self.data_rate);
+
+        let scale_bits = match self.gyro_scale {
+            GyroScale::Dps250 => ChipConstants::DPS_250_CFG_BITS,
+            GyroScale::Dps500 => ChipConstants::DPS_500_CFG_BITS,
+            GyroScale::Dps1000 => ChipConstants::DPS_1000_CFG_BITS,
+            GyroScale::Dps2000 => ChipConstants::DPS_2000_CFG_BITS,
+        };
+        let value = odr_bits | scale_bits;
+
+        log::debug!(target: LOG_TARGET, "CTRL2_G (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::CTRL2_G, "CTRL2_G", value)
+    }
+
+    /// Writes both CTRL1_XL and CTRL2_G, as the data rate is in each
+    fn update_data_rate(&mut self) -> AccelerometerResult<()> {
+        self.update_accel_config()
+            .and_then(|_| self.update_gyro_config())
+    }
+
+    fn read_register(&self, register: u8, name: &str) -> AccelerometerResult<u8> {
+        super::read_register(&self.i2c, self.address, register, name)
+    }
+
+    fn write_register(&mut self, register: u8, name: &str, value: u8) -> AccelerometerResult<()> {
+        super::write_register(&self.i2c, self.address, register, name, value)
+    }
+
+    fn read_triple(&self, register: u8, name: &str) -> AccelerometerResult<[u8; 6]> {
+        let mut data: [u8; 6] = [0; 6];
+
+        super::read_registers(&self.i2c, self.address, register, name, &mut data).map(|_| data)
+    }
+}
+
+impl<I2C> AccelerometerChip for Lsm6dsoxImpl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    fn default_chip_address(&self) -> u8 {
+        ChipConstants::DEFAULT_I2C_ADDRESS
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::LSM6DSOX
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        super::reconfigure(
+            self,
+            |chip| &mut chip.scale,
+            scale,
+            Self::update_accel_config,
+        )
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        // A CTRL2_G failure writes CTRL1_XL back too
+        super::reconfigure(
+            self,
+            |chip| &mut chip.data_rate,
+            data_rate,
+            Self::update_data_rate,
+        )
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.sample_period
+    }
+
+    fn raw_measurement(&self) -> AccelerometerResult<Value> {
+        self.read_triple(ChipConstants::OUTX_L_A, "OUTX_L_A")
+            .map(|data| Value {
+                x: to_meters_per_second(&self.scale, &data[0..2]),
+                y: to_meters_per_second(&self.scale, &data[2..4]),
+                z: to_meters_per_second(&self.scale, &data[4..6]),
+            })
+    }
+
+    fn angular_rate(&self) -> AccelerometerResult<Value> {
+        self.read_triple(ChipConstants::OUTX_L_G, "OUTX_L_G")
+            .map(|data| Value {
+                x: to_radians_per_second(&self.gyro_scale, &data[0..2]),
+                y: to_radians_per_second(&self.gyro_scale, &data[2..4]),
+                z: to_radians_per_second(&self.gyro_scale, &data[4..6]),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_data_rate, to_meters_per_second, to_radians_per_second};
+    use crate::{utils, GyroScale, OutputDataRate, Scale};
+
+    #[test]
+    fn nearest_supported_data_rate() {
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate800Hz),
+            (833.0, 0b0111_0000)
+        );
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate100Hz),
+            (104.0, 0b0100_0000)
+        );
+        assert_eq!(
+            nearest_data_rate(&OutputDataRate::DataRate1_56Hz),
+            (12.5, 0b0001_0000)
+        );
+    }
+
+    #[test]
+    fn little_endian_conversion() {
+        let value = to_meters_per_second(&Scale::TwoG, &1000i16.to_le_bytes());
+
+        assert!((value - 0.061 * utils::G_METERS_PER_SECOND).abs() < 1e-9);
+        assert!(
+            (to_radians_per_second(&GyroScale::Dps2000, &(-1000i16).to_le_bytes())
+                + 70.0f64.to_radians())
+            .abs()
+                < 1e-9
+        );
+    }
+}
@@ -1,19 +1,28 @@
 use crate::{
-    chips::AccelerometerChip, utils, AccelerometerError, AccelerometerResult, DeviceConfig,
-    OutputDataRate, Scale, Value,
+    chips::{AccelerometerChip, ChipProbe},
+    utils, AccelerometerError, AccelerometerResult, DeviceConfig, OutputDataRate, Scale,
+    SupportedChips, Value,
 };
 use embedded_hal::blocking::i2c;
 use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
-use std::{cell::RefCell, thread};
+use std::{cell::RefCell, thread, time};
 
 struct ChipConstants;
 impl ChipConstants {
     const DEFAULT_I2C_ADDRESS: u8 = 0x1d;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x1c;
+    const F_STATUS: u8 = 0x00;
     const OUT_X_MSB: u8 = 0x01;
+    const F_SETUP: u8 = 0x09;
+
+    const WHO_AM_I: u8 = 0x0d;
 
     const XYZ_DATA_CFG: u8 = 0x0e;
 
     const CTRL_REG1: u8 = 0x2a;
+    const CTRL_REG3: u8 = 0x2c;
+    const CTRL_REG4: u8 = 0x2d;
+    const CTRL_REG5: u8 = 0x2e;
 
     const TWO_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 1024.0;
     const FOUR_G_SCALE_FACTOR: f64 = utils::G_METERS_PER_SECOND / 512.0;
@@ -22,8 +31,34 @@ impl ChipConstants {
     const TWO_G_CFG_BITS: u8 = 0b00;
     const FOUR_G_CFG_BITS: u8 = 0b01;
     const EIGHT_G_CFG_BITS: u8 = 0b10;
+
+    const F_MODE_CIRCULAR_BITS: u8 = 0b01000000;
+    const F_STATUS_F_OVF_BITS: u8 = 0b10000000;
+    const F_STATUS_F_CNT_MASK: u8 = 0b00111111;
+
+    const CTRL_REG3_IPOL_BITS: u8 = 0b00000010; // Active high, push-pull
+    const CTRL_REG4_INT_EN_DRDY_BITS: u8 = 0b00000001;
+    const CTRL_REG5_INT_CFG_DRDY_BITS: u8 = 0b00000001; // Route to INT1
+
+    const MMA8451Q_WHO_AM_I: u8 = 0x1a;
+    const MMA8452Q_WHO_AM_I: u8 = 0x2a;
+    const MMA8453Q_WHO_AM_I: u8 = 0x3a;
 }
 
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::M845xQ,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::WHO_AM_I,
+    identities: &[
+        ChipConstants::MMA8451Q_WHO_AM_I,
+        ChipConstants::MMA8452Q_WHO_AM_I,
+        ChipConstants::MMA8453Q_WHO_AM_I,
+    ],
+};
+
 fn apply_scale(scale: &Scale, value: i16) -> f64 {
     match scale {
         Scale::TwoG => ChipConstants::TWO_G_SCALE_FACTOR * value as f64,
@@ -42,27 +77,36 @@ pub struct M845xQImpl<I2C> {
     scale: Scale,
 
     data_rate: OutputDataRate,
+
+    fifo: bool,
 }
 
 impl<I2C> M845xQImpl<I2C>
 where
     I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
 {
-    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> Self {
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
         let mut value = M845xQImpl {
             i2c: RefCell::new(i2c),
             address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
             scale: config.scale,
             data_rate: config.data_rate,
+            fifo: config.fifo,
         };
 
         value
             .update_scale()
             .and_then(|_| value.update_data_rate())
-            .and_then(|_| value.delay_for_update())
-            .unwrap();
-
-        value
+            .and_then(|_| {
+                if value.fifo {
+                    value.enable_fifo()
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|_| value.delay_for_update())?;
+
+        Ok(value)
     }
 
     fn update_scale(&mut self) -> Result<(), AccelerometerError> {
@@ -75,7 +119,10 @@ where
             Scale::FourG => ChipConstants::FOUR_G_CFG_BITS,
             Scale::EightG => ChipConstants::EIGHT_G_CFG_BITS,
             _ => {
-                return Err(AccelerometerError::NotSupportedByChip);
+                return Err(AccelerometerError::UnsupportedScale {
+                    chip: SupportedChips::M845xQ,
+                    scale: self.scale,
+                });
             }
         };
 
@@ -104,13 +151,80 @@ where
             .and(Ok(()))
     }
 
+    fn enable_fifo(&mut self) -> Result<(), AccelerometerError> {
+        // Of the family, only the MMA8451Q has a FIFO
+        let who_am_i = self.read_who_am_i()?;
+        if who_am_i != ChipConstants::MMA8451Q_WHO_AM_I {
+            return Err(AccelerometerError::NotSupportedByChip {
+                chip: SupportedChips::M845xQ,
+                operation: format!("FIFO (identity {:#04x} is not an MMA8451Q)", who_am_i),
+            });
+        }
+
+        // Per the documentation: F_SETUP may only be changed in STANDBY mode
+        self.standby()
+            .and_then(|_| self.write_f_setup(ChipConstants::F_MODE_CIRCULAR_BITS))
+            .and_then(|_| self.active())
+    }
+
+    fn read_who_am_i(&self) -> Result<u8, AccelerometerError> {
+        let mut data = [0];
+
+        self.i2c
+            .borrow_mut()
+            .write_read(self.address, &[ChipConstants::WHO_AM_I], &mut data)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read WHO_AM_I on {:#04x}", self.address),
+                source,
+            })
+            .and(Ok(data[0]))
+    }
+
+    fn read_f_status(&self) -> Result<u8, AccelerometerError> {
+        let mut data = [0];
+
+        self.i2c
+            .borrow_mut()
+            .write_read(self.address, &[ChipConstants::F_STATUS], &mut data)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read F_STATUS on {:#04x}", self.address),
+                source,
+            })
+            .and(Ok(data[0]))
+    }
+
+    fn write_f_setup(&mut self, value: u8) -> Result<(), AccelerometerError> {
+        self.i2c
+            .borrow_mut()
+            .write(self.address, &[ChipConstants::F_SETUP, value])
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("write F_SETUP on {:#04x}", self.address),
+                source,
+            })
+            .and(Ok(()))
+    }
+
+    fn write_ctrl_reg(&mut self, register: u8, name: &str, value: u8) -> AccelerometerResult<()> {
+        self.i2c
+            .borrow_mut()
+            .write(self.address, &[register, value])
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("write {} on {:#04x}", name, self.address),
+                source,
+            })
+            .and(Ok(()))
+    }
+
     fn read_ctrl_reg1(&self) -> Result<u8, AccelerometerError> {
         let mut data = [0];
 
         self.i2c
             .borrow_mut()
             .write_read(self.address, &[ChipConstants::CTRL_REG1], &mut data)
-            .map_err(AccelerometerError::I2CBusError)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read CTRL_REG1 on {:#04x}", self.address),
+                source,
+            })
             .and(Ok(data[0]))
     }
 
@@ -118,7 +232,10 @@ where
         self.i2c
             .borrow_mut()
             .write(self.address, &[ChipConstants::XYZ_DATA_CFG, value])
-            .map_err(AccelerometerError::I2CBusError)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("write XYZ_DATA_CFG on {:#04x}", self.address),
+                source,
+            })
             .and(Ok(()))
     }
 
@@ -126,7 +243,10 @@ where
         self.i2c
             .borrow_mut()
             .write(self.address, &[ChipConstants::CTRL_REG1, value])
-            .map_err(AccelerometerError::I2CBusError)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("write CTRL_REG1 on {:#04x}", self.address),
+                source,
+            })
             .and(Ok(()))
     }
 
@@ -169,16 +289,63 @@ where
         ChipConstants::DEFAULT_I2C_ADDRESS
     }
 
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::M845xQ
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        // Checked before touching the chip, so it keeps running as it was
+        if let Scale::SixteenG = scale {
+            return Err(AccelerometerError::NotSupportedByChip {
+                chip: SupportedChips::M845xQ,
+                operation: format!("{:?} scale", scale),
+            });
+        }
+
+        let previous = self.scale;
+        self.scale = scale;
+
+        self.update_scale().map_err(|e| {
+            // Best effort: write the previous scale back, which also takes
+            // the chip out of STANDBY if the failure left it there
+            self.scale = previous;
+            let _ = self.update_scale();
+            e
+        })
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        let previous = self.data_rate;
+        self.data_rate = data_rate;
+
+        self.update_data_rate()
+            .and_then(|_| self.delay_for_update())
+            .map_err(|e| {
+                // Best effort, as for set_scale
+                self.data_rate = previous;
+                let _ = self.update_data_rate();
+                e
+            })
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.data_rate.update_cycle_duration()
+    }
+
     fn average(&self, sample_count: u8) -> AccelerometerResult<Value> {
         let mut avg: Value = Default::default();
 
         for c in 0..sample_count {
-            let m = self.raw_measurement().unwrap();
+            let m = self.raw_measurement()?;
 
             log::debug!(target: "acclrmtr", "Zero sample {}: {:?}", c, m);
             avg.mut_add(&m);
 
-            self.delay_for_update().unwrap();
+            self.delay_for_update()?;
         }
 
         avg.mut_div(sample_count as f64);
@@ -192,11 +359,78 @@ where
         self.i2c
             .borrow_mut()
             .write_read(self.address, &[ChipConstants::OUT_X_MSB], &mut data)
-            .map_err(AccelerometerError::I2CBusError)
+            .map_err(|source| AccelerometerError::I2CBusError {
+                operation: format!("read OUT_X_MSB on {:#04x}", self.address),
+                source,
+            })
             .and(Ok(Value {
                 x: self.to_meters_per_second(&data[0..2]),
                 y: self.to_meters_per_second(&data[2..4]),
                 z: self.to_meters_per_second(&data[4..6]),
             }))
     }
+
+    fn drain_fifo(&self) -> AccelerometerResult<Vec<Value>> {
+        if !self.fifo {
+            return Err(AccelerometerError::NotSupportedByChip {
+                chip: SupportedChips::M845xQ,
+                operation: "FIFO readout (disabled in configuration)".to_string(),
+            });
+        }
+
+        let f_status = self.read_f_status()?;
+        if f_status & ChipConstants::F_STATUS_F_OVF_BITS != 0 {
+            log::warn!(target: "acclrmtr", "FIFO overflow; the oldest samples were lost");
+        }
+
+        // While the FIFO is enabled, OUT_X_MSB reads consecutive FIFO samples
+        let count = (f_status & ChipConstants::F_STATUS_F_CNT_MASK) as usize;
+        let mut data = vec![0; count * 6];
+
+        if count > 0 {
+            self.i2c
+                .borrow_mut()
+                .write_read(self.address, &[ChipConstants::OUT_X_MSB], &mut data)
+                .map_err(|source| AccelerometerError::I2CBusError {
+                    operation: format!("read {} FIFO samples on {:#04x}", count, self.address),
+                    source,
+                })?;
+        }
+
+        Ok(data
+            .chunks_exact(6)
+            .map(|sample| Value {
+                x: self.to_meters_per_second(&sample[0..2]),
+                y: self.to_meters_per_second(&sample[2..4]),
+                z: self.to_meters_per_second(&sample[4..6]),
+            })
+            .collect())
+    }
+
+    fn enable_data_ready_interrupt(&mut self) -> AccelerometerResult<()> {
+        // Per the documentation: CTRL_REG3..5 may only be changed in STANDBY mode
+        self.standby()
+            .and_then(|_| {
+                self.write_ctrl_reg(
+                    ChipConstants::CTRL_REG3,
+                    "CTRL_REG3",
+                    ChipConstants::CTRL_REG3_IPOL_BITS,
+                )
+            })
+            .and_then(|_| {
+                self.write_ctrl_reg(
+                    ChipConstants::CTRL_REG4,
+                    "CTRL_REG4",
+                    ChipConstants::CTRL_REG4_INT_EN_DRDY_BITS,
+                )
+            })
+            .and_then(|_| {
+                self.write_ctrl_reg(
+                    ChipConstants::CTRL_REG5,
+                    "CTRL_REG5",
+                    ChipConstants::CTRL_REG5_INT_CFG_DRDY_BITS,
+                )
+            })
+            .and_then(|_| self.active())
+    }
 }
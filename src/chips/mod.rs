@@ -1,32 +1,500 @@
-use linux_embedded_hal::I2cdev;
+use std::{cell::RefCell, mem, thread, time};
 
-use crate::{AccelerometerResult, DeviceConfig, SupportedChips, Value};
+use embedded_hal::blocking::i2c;
+use linux_embedded_hal::{i2cdev::linux::LinuxI2CError, I2cdev};
 
+use crate::{
+    AccelerometerError, AccelerometerResult, DeviceConfig, OutputDataRate, Scale, SupportedChips,
+    Value,
+};
+
+mod adxl345;
+mod bmi160;
 mod icm20948;
+mod invensense;
+mod lis3dh;
+mod lsm6dsox;
 mod m845xq;
+mod mpu6050;
+
+/// How to recognize a chip on the I²C bus (see [SupportedChips::Auto])
+pub(crate) struct ChipProbe {
+    pub(crate) chip: SupportedChips,
+
+    /// Addresses the chip may be strapped to, in order of likelihood
+    pub(crate) addresses: &'static [u8],
+
+    /// Register holding the chip's identity
+    pub(crate) who_am_i_register: u8,
+
+    /// Identity values reported by members of the chip family
+    pub(crate) identities: &'static [u8],
+}
+
+/// NOTE: Order matters where chips share addresses: on an MPU-6050/MPU-9250,
+///       register 0x00 holds a factory self-test trim (that may read as an
+///       ICM-20948 or BMI160 identity), so its WHO_AM_I (0x75) goes first
+const PROBES: [ChipProbe; 7] = [
+    m845xq::PROBE,
+    mpu6050::PROBE,
+    icm20948::PROBE,
+    adxl345::PROBE,
+    lis3dh::PROBE,
+    bmi160::PROBE,
+    lsm6dsox::PROBE,
+];
 
 impl SupportedChips {
-    pub(crate) fn new(&self, config: &DeviceConfig) -> Box<dyn AccelerometerChip> {
-        let i2c = I2cdev::new(&config.i2c_device_file).unwrap_or_else(|_| {
-            panic!("Unable to load I²C device file: {}", config.i2c_device_file)
-        });
-
-        let chip: Box<dyn AccelerometerChip> = match &*self {
-            SupportedChips::M845xQ => Box::new(m845xq::M845xQImpl::new(i2c, config)),
-            SupportedChips::ICM20948 => Box::new(icm20948::Icm20948Impl::new(i2c, config)),
+    pub(crate) fn new(
+        &self,
+        config: &DeviceConfig,
+    ) -> AccelerometerResult<Box<dyn AccelerometerChip>> {
+        let i2c = I2cdev::new(&config.i2c_device_file).map_err(|source| {
+            AccelerometerError::DeviceFileError {
+                path: config.i2c_device_file.clone(),
+                source,
+            }
+        })?;
+
+        self.new_on_bus(i2c, config)
+    }
+
+    /// Like [SupportedChips::new], on an already opened I²C bus
+    fn new_on_bus<I2C>(
+        &self,
+        mut i2c: I2C,
+        config: &DeviceConfig,
+    ) -> AccelerometerResult<Box<dyn AccelerometerChip>>
+    where
+        I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError> + 'static,
+    {
+        let config = match self {
+            SupportedChips::Auto => {
+                let (chip, address) = detect(&mut i2c, config.address).ok_or_else(|| {
+                    AccelerometerError::ChipNotDetected {
+                        path: config.i2c_device_file.clone(),
+                    }
+                })?;
+
+                DeviceConfig {
+                    chip,
+                    address: Some(address),
+                    ..config.clone()
+                }
+            }
+            _ => config.clone(),
+        };
+
+        let chip: Box<dyn AccelerometerChip> = match config.chip {
+            SupportedChips::M845xQ => Box::new(m845xq::M845xQImpl::new(i2c, &config)?),
+            SupportedChips::ICM20948 => Box::new(icm20948::Icm20948Impl::new(i2c, &config)?),
+            SupportedChips::ADXL345 => Box::new(adxl345::Adxl345Impl::new(i2c, &config)?),
+            SupportedChips::LIS3DH => Box::new(lis3dh::Lis3dhImpl::new(i2c, &config)?),
+            SupportedChips::MPU6050 => Box::new(mpu6050::Mpu6050Impl::new(i2c, &config)?),
+            SupportedChips::BMI160 => Box::new(bmi160::Bmi160Impl::new(i2c, &config)?),
+            SupportedChips::LSM6DSOX => Box::new(lsm6dsox::Lsm6dsoxImpl::new(i2c, &config)?),
+            SupportedChips::Auto => unreachable!("Auto is resolved by detect()"),
         };
 
         config.log_info(chip.as_ref().default_chip_address());
 
-        chip
+        Ok(chip)
     }
 }
 
+/// Probes each supported chip's candidate addresses (or only `address`, if
+/// given) and returns the first chip whose identity register matches.
+///
+/// NOTE: The probe only reads registers; the ICM-20948 is expected to be in
+///       user bank 0, which is its power-on default.
+fn detect<I2C>(i2c: &mut I2C, address: Option<u8>) -> Option<(SupportedChips, u8)>
+where
+    I2C: i2c::WriteRead<Error = LinuxI2CError>,
+{
+    for probe in PROBES.iter() {
+        let candidates = match address {
+            Some(address) => vec![address],
+            None => probe.addresses.to_vec(),
+        };
+
+        for candidate in candidates {
+            let mut data = [0];
+
+            match i2c.write_read(candidate, &[probe.who_am_i_register], &mut data) {
+                Ok(_) if probe.identities.contains(&data[0]) => {
+                    log::info!(target: "acclrmtr",
+                        "Detected {:?} (identity {:#04x}) at {:#04x}",
+                        probe.chip,
+                        data[0],
+                        candidate
+                    );
+
+                    return Some((probe.chip, candidate));
+                }
+                Ok(_) => {
+                    log::debug!(target: "acclrmtr",
+                        "Not a {:?} at {:#04x}: identity {:#04x}",
+                        probe.chip,
+                        candidate,
+                        data[0]
+                    );
+                }
+                Err(e) => {
+                    log::debug!(target: "acclrmtr",
+                        "No response from {:#04x} while probing for {:?}: {}",
+                        candidate,
+                        probe.chip,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Reads `buffer.len()` registers, starting at `register` (`name` in errors),
+/// of the chip at `address` in one transaction
+pub(crate) fn read_registers<I2C>(
+    i2c: &RefCell<I2C>,
+    address: u8,
+    register: u8,
+    name: &str,
+    buffer: &mut [u8],
+) -> AccelerometerResult<()>
+where
+    I2C: i2c::WriteRead<Error = LinuxI2CError>,
+{
+    i2c.borrow_mut()
+        .write_read(address, &[register], buffer)
+        .map_err(|source| AccelerometerError::I2CBusError {
+            operation: format!("read {} on {:#04x}", name, address),
+            source,
+        })
+}
+
+/// Reads `register` (`name` in errors) of the chip at `address`
+pub(crate) fn read_register<I2C>(
+    i2c: &RefCell<I2C>,
+    address: u8,
+    register: u8,
+    name: &str,
+) -> AccelerometerResult<u8>
+where
+    I2C: i2c::WriteRead<Error = LinuxI2CError>,
+{
+    let mut data = [0];
+
+    read_registers(i2c, address, register, name, &mut data).map(|_| data[0])
+}
+
+/// Writes `value` to `register` (`name` in errors) of the chip at `address`
+pub(crate) fn write_register<I2C>(
+    i2c: &RefCell<I2C>,
+    address: u8,
+    register: u8,
+    name: &str,
+    value: u8,
+) -> AccelerometerResult<()>
+where
+    I2C: i2c::Write<Error = LinuxI2CError>,
+{
+    i2c.borrow_mut()
+        .write(address, &[register, value])
+        .map_err(|source| AccelerometerError::I2CBusError {
+            operation: format!("write {} on {:#04x}", name, address),
+            source,
+        })
+}
+
+/// The entry of `rates` (as (Hz, register bits)) nearest to `data_rate`
+pub(crate) fn nearest_rate(rates: &[(f64, u8)], data_rate: &OutputDataRate) -> (f64, u8) {
+    let desired_hz = data_rate.update_frequency_hz();
+
+    rates
+        .iter()
+        .copied()
+        .min_by(|(a, _), (b, _)| (a - desired_hz).abs().total_cmp(&(b - desired_hz).abs()))
+        .unwrap()
+}
+
+/// Changes one of `chip`'s settings to `value` and programs it with `apply`.
+/// On failure the previous setting is restored and (best effort) programmed
+/// again, so the chip keeps running as it was.
+pub(crate) fn reconfigure<C, T>(
+    chip: &mut C,
+    setting: fn(&mut C) -> &mut T,
+    value: T,
+    apply: fn(&mut C) -> AccelerometerResult<()>,
+) -> AccelerometerResult<()> {
+    let previous = mem::replace(setting(chip), value);
+
+    apply(chip).map_err(|e| {
+        *setting(chip) = previous;
+        let _ = apply(chip);
+        e
+    })
+}
+
 pub(crate) trait AccelerometerChip {
     fn default_chip_address(&self) -> u8;
 
+    /// The I²C address the chip is being accessed at
+    fn address(&self) -> u8;
+
+    /// The concrete chip type (never [SupportedChips::Auto])
+    fn chip_type(&self) -> SupportedChips;
+
     /// Returns a (current) raw measurement from the accelerometer
     fn raw_measurement(&self) -> AccelerometerResult<Value>;
 
-    fn average(&self, sample_count: u8) -> AccelerometerResult<Value>;
+    /// Averages `sample_count` raw measurements, one sample period apart
+    fn average(&self, sample_count: u8) -> AccelerometerResult<Value> {
+        let mut avg: Value = Default::default();
+
+        for c in 0..sample_count {
+            let m = self.raw_measurement()?;
+
+            log::debug!(target: "acclrmtr", "Zero sample {}: {:?}", c, m);
+            avg.mut_add(&m);
+
+            thread::sleep(self.sample_period());
+        }
+
+        avg.mut_div(sample_count as f64);
+
+        Ok(avg)
+    }
+
+    /// The time between samples at the chip's (actual) output data rate
+    fn sample_period(&self) -> time::Duration;
+
+    /// Returns a (current) angular rate, in rad/s, from the gyroscope
+    fn angular_rate(&self) -> AccelerometerResult<Value> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "Gyroscope readout".to_string(),
+        })
+    }
+
+    /// Returns a (current) magnetic field, in µT, from the magnetometer
+    fn magnetic_field(&self) -> AccelerometerResult<Value> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "Magnetometer readout".to_string(),
+        })
+    }
+
+    /// Returns the (current) on-die temperature, in °C
+    fn temperature(&self) -> AccelerometerResult<f64> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "Temperature readout".to_string(),
+        })
+    }
+
+    /// Returns (and removes) all raw measurements buffered in the hardware
+    /// FIFO, oldest first
+    fn drain_fifo(&self) -> AccelerometerResult<Vec<Value>> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "FIFO readout".to_string(),
+        })
+    }
+
+    /// Changes the full scale range of the running chip
+    fn set_scale(&mut self, _scale: Scale) -> AccelerometerResult<()> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "Scale change".to_string(),
+        })
+    }
+
+    /// Changes the output data rate of the running chip
+    fn set_data_rate(&mut self, _data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "Data rate change".to_string(),
+        })
+    }
+
+    /// Routes an active-high data-ready signal to the chip's (first) interrupt pin
+    fn enable_data_ready_interrupt(&mut self) -> AccelerometerResult<()> {
+        Err(AccelerometerError::NotSupportedByChip {
+            chip: self.chip_type(),
+            operation: "Data-ready interrupt".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io};
+
+    use embedded_hal::blocking::i2c;
+    use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+    use super::{detect, reconfigure};
+    use crate::{AccelerometerError, AccelerometerResult, DeviceConfig, SupportedChips};
+
+    /// A bus where only the given (address, register) pairs respond
+    struct FakeBus {
+        registers: HashMap<(u8, u8), u8>,
+    }
+
+    impl FakeBus {
+        fn new(registers: &[((u8, u8), u8)]) -> Self {
+            FakeBus {
+                registers: registers.iter().copied().collect(),
+            }
+        }
+    }
+
+    impl i2c::Write for FakeBus {
+        type Error = LinuxI2CError;
+
+        /// Stores the written bytes, so they can be read back
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+            for (offset, value) in bytes[1..].iter().enumerate() {
+                self.registers
+                    .insert((address, bytes[0] + offset as u8), *value);
+            }
+
+            Ok(())
+        }
+    }
+
+    impl i2c::WriteRead for FakeBus {
+        type Error = LinuxI2CError;
+
+        fn write_read(
+            &mut self,
+            address: u8,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), Self::Error> {
+            match self.registers.get(&(address, bytes[0])) {
+                Some(value) => {
+                    buffer[0] = *value;
+                    Ok(())
+                }
+                None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+            }
+        }
+    }
+
+    #[test]
+    fn detect_m845xq_alternate_address() {
+        let mut bus = FakeBus::new(&[((0x1c, 0x0d), 0x2a)]);
+
+        assert_eq!(detect(&mut bus, None), Some((SupportedChips::M845xQ, 0x1c)));
+    }
+
+    #[test]
+    fn detect_icm20948() {
+        let mut bus = FakeBus::new(&[((0x68, 0x00), 0xea)]);
+
+        assert_eq!(
+            detect(&mut bus, None),
+            Some((SupportedChips::ICM20948, 0x68))
+        );
+    }
+
+    #[test]
+    fn detect_mpu9250_despite_self_test_trim() {
+        // SELF_TEST_X_GYRO (0x00) happens to match the ICM-20948 and BMI160
+        for trim in [0xea, 0xd1] {
+            let mut bus = FakeBus::new(&[((0x68, 0x00), trim), ((0x68, 0x75), 0x71)]);
+
+            assert_eq!(
+                detect(&mut bus, None),
+                Some((SupportedChips::MPU6050, 0x68))
+            );
+        }
+    }
+
+    #[test]
+    fn detect_bmi160() {
+        let mut bus = FakeBus::new(&[((0x69, 0x00), 0xd1)]);
+
+        assert_eq!(detect(&mut bus, None), Some((SupportedChips::BMI160, 0x69)));
+    }
+
+    #[test]
+    fn detect_ignores_unknown_identity() {
+        let mut bus = FakeBus::new(&[((0x1d, 0x0d), 0x00), ((0x68, 0x00), 0x12)]);
+
+        assert_eq!(detect(&mut bus, None), None);
+    }
+
+    #[test]
+    fn detect_only_probes_configured_address() {
+        let mut bus = FakeBus::new(&[((0x1d, 0x0d), 0x1a), ((0x69, 0x00), 0xea)]);
+
+        assert_eq!(
+            detect(&mut bus, Some(0x69)),
+            Some((SupportedChips::ICM20948, 0x69))
+        );
+    }
+
+    #[test]
+    fn identity_mismatch_is_an_error() {
+        let bus = FakeBus::new(&[((0x68, 0x00), 0x12)]);
+        let config = DeviceConfig {
+            i2c_device_file: "/dev/null".to_string(),
+            address: None,
+            chip: SupportedChips::ICM20948,
+            scale: Default::default(),
+            data_rate: Default::default(),
+            gyro_scale: Default::default(),
+            magnetometer: false,
+            fifo: false,
+            interrupt: None,
+        };
+
+        assert!(matches!(
+            SupportedChips::ICM20948.new_on_bus(bus, &config),
+            Err(AccelerometerError::IdentityMismatch {
+                chip: SupportedChips::ICM20948,
+                address: 0x68,
+                expected: &[0xea],
+                actual: 0x12,
+            })
+        ));
+    }
+
+    /// A chip whose setting can't be programmed to `rejected`
+    struct FakeChip {
+        setting: u8,
+        rejected: u8,
+        programmed: Vec<u8>,
+    }
+
+    impl FakeChip {
+        fn program(&mut self) -> AccelerometerResult<()> {
+            self.programmed.push(self.setting);
+
+            if self.setting == self.rejected {
+                Err(AccelerometerError::InvalidInputDataError(String::new()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn reconfigure_writes_previous_setting_back() {
+        let mut chip = FakeChip {
+            setting: 1,
+            rejected: 3,
+            programmed: vec![],
+        };
+
+        assert!(reconfigure(&mut chip, |c| &mut c.setting, 2, FakeChip::program).is_ok());
+        assert!(reconfigure(&mut chip, |c| &mut c.setting, 3, FakeChip::program).is_err());
+
+        assert_eq!(chip.setting, 2);
+        assert_eq!(chip.programmed, vec![2, 3, 2]);
+    }
 }
@@ -0,0 +1,290 @@
+use std::{cell::RefCell, thread, time};
+
+use embedded_hal::blocking::i2c;
+use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+use crate::{
+    AccelerometerError, AccelerometerResult, DeviceConfig, GyroScale, OutputDataRate, Scale,
+    SupportedChips, Value,
+};
+
+use super::{invensense, AccelerometerChip, ChipProbe};
+
+struct ChipConstants;
+impl ChipConstants {
+    // NOTE: The AD0 pin selects 0x68 (low) or 0x69 (high)
+    const DEFAULT_I2C_ADDRESS: u8 = 0x68;
+    const ALTERNATE_I2C_ADDRESS: u8 = 0x69;
+
+    const SMPLRT_DIV: u8 = 0x19;
+    const CONFIG: u8 = 0x1a;
+    const GYRO_CONFIG: u8 = 0x1b;
+    const ACCEL_CONFIG: u8 = 0x1c;
+    const ACCEL_XOUT_H: u8 = 0x3b;
+    const GYRO_XOUT_H: u8 = 0x43;
+    const PWR_MGMT_1: u8 = 0x6b;
+    const WHO_AM_I: u8 = 0x75;
+
+    const MPU6050_WHO_AM_I: u8 = 0x68;
+    const MPU9250_WHO_AM_I: u8 = 0x71;
+
+    const PWR_MGMT_1_RESET_BITS: u8 = 0b10000000;
+    // Clears SLEEP and clocks from the X gyroscope's PLL
+    const PWR_MGMT_1_WAKE_BITS: u8 = 0b00000001;
+
+    const TWO_G_CFG_BITS: u8 = 0b00000000;
+    const FOUR_G_CFG_BITS: u8 = 0b00001000;
+    const EIGHT_G_CFG_BITS: u8 = 0b00010000;
+    const SIXTEEN_G_CFG_BITS: u8 = 0b00011000;
+
+    const DPS_250_CFG_BITS: u8 = 0b00000000;
+    const DPS_500_CFG_BITS: u8 = 0b00001000;
+    const DPS_1000_CFG_BITS: u8 = 0b00010000;
+    const DPS_2000_CFG_BITS: u8 = 0b00011000;
+
+    // With the DLPF enabled, the sample rate is 1 kHz / (1 + SMPLRT_DIV)
+    const BASE_SAMPLE_RATE_HZ: f64 = 1000.0;
+    const MAX_SMPLRT_DIV: u8 = 0xff;
+
+    /// DLPF_CFG settings, as (accelerometer bandwidth in Hz, CONFIG bits),
+    /// widest first
+    const DLPF_BANDWIDTHS: [(f64, u8); 6] = [
+        (184.0, 1),
+        (94.0, 2),
+        (44.0, 3),
+        (21.0, 4),
+        (10.0, 5),
+        (5.0, 6),
+    ];
+}
+
+const LOG_TARGET: &str = "mpu6050";
+
+pub(crate) const PROBE: ChipProbe = ChipProbe {
+    chip: SupportedChips::MPU6050,
+    addresses: &[
+        ChipConstants::DEFAULT_I2C_ADDRESS,
+        ChipConstants::ALTERNATE_I2C_ADDRESS,
+    ],
+    who_am_i_register: ChipConstants::WHO_AM_I,
+    identities: &[
+        ChipConstants::MPU6050_WHO_AM_I,
+        ChipConstants::MPU9250_WHO_AM_I,
+    ],
+};
+
+pub(crate) struct Mpu6050Impl<I2C> {
+    i2c: RefCell<I2C>,
+    address: u8,
+    scale: Scale,
+    data_rate: OutputDataRate,
+    gyro_scale: GyroScale,
+
+    /// The time between samples at the data rate actually programmed
+    sample_period: time::Duration,
+}
+
+/// The SMPLRT_DIV whose sample rate is nearest to `data_rate`
+fn sample_rate_divider(data_rate: &OutputDataRate) -> u8 {
+    invensense::nearest_divider(
+        ChipConstants::BASE_SAMPLE_RATE_HZ,
+        ChipConstants::MAX_SMPLRT_DIV.into(),
+        data_rate,
+    ) as u8
+}
+
+/// The DLPF_CFG with the widest bandwidth below the Nyquist frequency of
+/// `sample_rate_hz` (or the narrowest, if none is)
+fn dlpf_bits(sample_rate_hz: f64) -> u8 {
+    invensense::dlpf_setting(&ChipConstants::DLPF_BANDWIDTHS, sample_rate_hz)
+}
+
+impl<I2C> Mpu6050Impl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    pub(crate) fn new(i2c: I2C, config: &DeviceConfig) -> AccelerometerResult<Self> {
+        let mut chip = Mpu6050Impl {
+            i2c: RefCell::new(i2c),
+            address: config.address.unwrap_or(ChipConstants::DEFAULT_I2C_ADDRESS),
+            scale: config.scale,
+            data_rate: config.data_rate,
+            gyro_scale: config.gyro_scale,
+            sample_period: config.data_rate.update_cycle_duration(),
+        };
+
+        chip.verify_identity()
+            .and_then(|_| chip.reset())
+            .and_then(|_| chip.wake())
+            .and_then(|_| chip.update_scale())
+            .and_then(|_| chip.update_data_rate())
+            .and_then(|_| chip.update_gyro_config())?;
+
+        Ok(chip)
+    }
+
+    fn verify_identity(&self) -> AccelerometerResult<()> {
+        self.read_register(ChipConstants::WHO_AM_I, "WHO_AM_I")
+            .and_then(|who_am_i| match who_am_i {
+                ChipConstants::MPU6050_WHO_AM_I => {
+                    log::info!(target: LOG_TARGET, "Found an MPU-6050");
+                    Ok(())
+                }
+                ChipConstants::MPU9250_WHO_AM_I => {
+                    log::info!(target: LOG_TARGET, "Found an MPU-9250");
+                    Ok(())
+                }
+                _ => Err(AccelerometerError::IdentityMismatch {
+                    chip: SupportedChips::MPU6050,
+                    address: self.address,
+                    expected: PROBE.identities,
+                    actual: who_am_i,
+                }),
+            })
+    }
+
+    fn reset(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::PWR_MGMT_1,
+            "PWR_MGMT_1",
+            ChipConstants::PWR_MGMT_1_RESET_BITS,
+        )
+        .map(|_| thread::sleep(time::Duration::from_secs_f64(0.1)))
+    }
+
+    fn wake(&mut self) -> AccelerometerResult<()> {
+        self.write_register(
+            ChipConstants::PWR_MGMT_1,
+            "PWR_MGMT_1",
+            ChipConstants::PWR_MGMT_1_WAKE_BITS,
+        )
+    }
+
+    fn update_scale(&mut self) -> AccelerometerResult<()> {
+        let value = match self.scale {
+            Scale::TwoG => ChipConstants::TWO_G_CFG_BITS,
+            Scale::FourG => ChipConstants::FOUR_G_CFG_BITS,
+            Scale::EightG => ChipConstants::EIGHT_G_CFG_BITS,
+            Scale::SixteenG => ChipConstants::SIXTEEN_G_CFG_BITS,
+        };
+
+        log::debug!(target: LOG_TARGET, "ACCEL_CONFIG (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::ACCEL_CONFIG, "ACCEL_CONFIG", value)
+    }
+
+    fn update_data_rate(&mut self) -> AccelerometerResult<()> {
+        // NOTE: The MPU-9250 filters its accelerometer via ACCEL_CONFIG_2,
+        //       which is left at its default; CONFIG filters its gyroscope
+        let divider = sample_rate_divider(&self.data_rate);
+        let actual_rate_hz =
+            invensense::divided_rate_hz(ChipConstants::BASE_SAMPLE_RATE_HZ, divider.into());
+        let dlpf = dlpf_bits(actual_rate_hz);
+
+        log::debug!(
+            target: LOG_TARGET,
+            "SMPLRT_DIV (desired): {} ({:.2} Hz for {:?}), DLPF_CFG: {}",
+            divider,
+            actual_rate_hz,
+            self.data_rate,
+            dlpf
+        );
+
+        self.write_register(ChipConstants::CONFIG, "CONFIG", dlpf)
+            .and_then(|_| self.write_register(ChipConstants::SMPLRT_DIV, "SMPLRT_DIV", divider))
+            .map(|_| {
+                self.sample_period = time::Duration::from_secs_f64(1.0 / actual_rate_hz);
+            })
+    }
+
+    fn update_gyro_config(&mut self) -> AccelerometerResult<()> {
+        let value = match self.gyro_scale {
+            GyroScale::Dps250 => ChipConstants::DPS_250_CFG_BITS,
+            GyroScale::Dps500 => ChipConstants::DPS_500_CFG_BITS,
+            GyroScale::Dps1000 => ChipConstants::DPS_1000_CFG_BITS,
+            GyroScale::Dps2000 => ChipConstants::DPS_2000_CFG_BITS,
+        };
+
+        log::debug!(target: LOG_TARGET, "GYRO_CONFIG (desired): {:#010b}", value);
+
+        self.write_register(ChipConstants::GYRO_CONFIG, "GYRO_CONFIG", value)
+    }
+
+    fn read_register(&self, register: u8, name: &str) -> AccelerometerResult<u8> {
+        super::read_register(&self.i2c, self.address, register, name)
+    }
+
+    fn write_register(&mut self, register: u8, name: &str, value: u8) -> AccelerometerResult<()> {
+        super::write_register(&self.i2c, self.address, register, name, value)
+    }
+
+    fn read_triple(&self, register: u8, name: &str) -> AccelerometerResult<[u8; 6]> {
+        let mut data: [u8; 6] = [0; 6];
+
+        super::read_registers(&self.i2c, self.address, register, name, &mut data).map(|_| data)
+    }
+}
+
+impl<I2C> AccelerometerChip for Mpu6050Impl<I2C>
+where
+    I2C: i2c::Write<Error = LinuxI2CError> + i2c::WriteRead<Error = LinuxI2CError>,
+{
+    fn default_chip_address(&self) -> u8 {
+        ChipConstants::DEFAULT_I2C_ADDRESS
+    }
+
+    fn address(&self) -> u8 {
+        self.address
+    }
+
+    fn chip_type(&self) -> SupportedChips {
+        SupportedChips::MPU6050
+    }
+
+    fn set_scale(&mut self, scale: Scale) -> AccelerometerResult<()> {
+        super::reconfigure(self, |chip| &mut chip.scale, scale, Self::update_scale)
+    }
+
+    fn set_data_rate(&mut self, data_rate: OutputDataRate) -> AccelerometerResult<()> {
+        super::reconfigure(
+            self,
+            |chip| &mut chip.data_rate,
+            data_rate,
+            Self::update_data_rate,
+        )
+    }
+
+    fn sample_period(&self) -> time::Duration {
+        self.sample_period
+    }
+
+    fn raw_measurement(&self) -> AccelerometerResult<Value> {
+        self.read_triple(ChipConstants::ACCEL_XOUT_H, "ACCEL_XOUT_H")
+            .map(|data| Value {
+                x: invensense::to_meters_per_second(&self.scale, &data[0..2]),
+                y: invensense::to_meters_per_second(&self.scale, &data[2..4]),
+                z: invensense::to_meters_per_second(&self.scale, &data[4..6]),
+            })
+    }
+
+    fn angular_rate(&self) -> AccelerometerResult<Value> {
+        self.read_triple(ChipConstants::GYRO_XOUT_H, "GYRO_XOUT_H")
+            .map(|data| Value {
+                x: invensense::to_radians_per_second(&self.gyro_scale, &data[0..2]),
+                y: invensense::to_radians_per_second(&self.gyro_scale, &data[2..4]),
+                z: invensense::to_radians_per_second(&self.gyro_scale, &data[4..6]),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dlpf_bits;
+
+    #[test]
+    fn dlpf_below_nyquist() {
+        assert_eq!(dlpf_bits(1000.0), 1);
+        assert_eq!(dlpf_bits(100.0), 3);
+        assert_eq!(dlpf_bits(6.25), 6);
+    }
+}
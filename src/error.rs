@@ -0,0 +1,186 @@
+use std::{error, fmt, io};
+
+use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+
+use crate::AccelerometerError;
+
+impl fmt::Display for AccelerometerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccelerometerError::I2CBusError { operation, source } => {
+                write!(f, "I²C bus error during {}: {}", operation, source)
+            }
+            AccelerometerError::DeviceFileError { path, source } => {
+                write!(f, "Unable to open I²C device file {}: {}", path, source)
+            }
+            AccelerometerError::ChipNotDetected { path } => {
+                write!(f, "No supported accelerometer detected on {}", path)
+            }
+            AccelerometerError::IdentityMismatch {
+                chip,
+                address,
+                expected,
+                actual,
+            } => {
+                let expected: Vec<_> = expected.iter().map(|e| format!("{:#04x}", e)).collect();
+
+                write!(
+                    f,
+                    "{:?} at {:#04x} reported identity {:#04x}, expected {}",
+                    chip,
+                    address,
+                    actual,
+                    expected.join(" or ")
+                )
+            }
+            AccelerometerError::RegisterMismatch {
+                chip,
+                address,
+                register,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{:?} at {:#04x} reads back {} as {:#04x}, expected {:#04x}",
+                chip, address, register, actual, expected
+            ),
+            AccelerometerError::UnsupportedScale { chip, scale } => {
+                write!(f, "Scale {:?} is not supported by {:?}", scale, chip)
+            }
+            AccelerometerError::NotSupportedByChip { chip, operation } => {
+                write!(f, "{} is not supported by {:?}", operation, chip)
+            }
+            AccelerometerError::GpioError { operation, source } => {
+                write!(f, "GPIO error during {}: {}", operation, source)
+            }
+            AccelerometerError::InvalidInputDataError(description) => {
+                write!(f, "Invalid input data: {}", description)
+            }
+        }
+    }
+}
+
+impl AccelerometerError {
+    /// A short, stable name for the kind of error (e.g, for metric labels)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AccelerometerError::I2CBusError { .. } => "i2c_bus",
+            AccelerometerError::DeviceFileError { .. } => "device_file",
+            AccelerometerError::ChipNotDetected { .. } => "chip_not_detected",
+            AccelerometerError::IdentityMismatch { .. } => "identity_mismatch",
+            AccelerometerError::RegisterMismatch { .. } => "register_mismatch",
+            AccelerometerError::UnsupportedScale { .. } => "unsupported_scale",
+            AccelerometerError::NotSupportedByChip { .. } => "not_supported_by_chip",
+            AccelerometerError::GpioError { .. } => "gpio",
+            AccelerometerError::InvalidInputDataError(_) => "invalid_input_data",
+        }
+    }
+
+    /// A short name for the underlying cause of an I²C bus error (e.g,
+    /// "enxio" for an errno, or "timed_out" for an [io::ErrorKind])
+    pub fn cause(&self) -> Option<String> {
+        match self {
+            AccelerometerError::I2CBusError { source, .. } => Some(match source {
+                LinuxI2CError::Nix(errno) => format!("{:?}", errno).to_lowercase(),
+                LinuxI2CError::Io(e) => io_error_kind_name(e.kind()),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `kind` in snake case (e.g, "timed_out" for [io::ErrorKind::TimedOut])
+fn io_error_kind_name(kind: io::ErrorKind) -> String {
+    let mut name = String::new();
+
+    for c in format!("{:?}", kind).chars() {
+        if c.is_uppercase() && !name.is_empty() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+
+    name
+}
+
+impl error::Error for AccelerometerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AccelerometerError::I2CBusError { source, .. } => Some(source),
+            AccelerometerError::DeviceFileError { source, .. } => Some(source),
+            AccelerometerError::GpioError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, io};
+
+    use crate::{AccelerometerError, Scale, SupportedChips};
+
+    #[test]
+    fn display_i2c_bus_error() {
+        let uut = AccelerometerError::I2CBusError {
+            operation: "write CTRL_REG1 on 0x1d".to_string(),
+            source: io::Error::new(io::ErrorKind::Other, "Remote I/O error").into(),
+        };
+
+        assert_eq!(
+            uut.to_string(),
+            "I²C bus error during write CTRL_REG1 on 0x1d: Remote I/O error"
+        );
+    }
+
+    #[test]
+    fn source_chains_to_i2c_error() {
+        let uut = AccelerometerError::I2CBusError {
+            operation: "read OUT_X_MSB on 0x1d".to_string(),
+            source: io::Error::new(io::ErrorKind::Other, "Remote I/O error").into(),
+        };
+
+        assert_eq!(uut.source().unwrap().to_string(), "Remote I/O error");
+    }
+
+    #[test]
+    fn i2c_bus_error_cause() {
+        let uut = AccelerometerError::I2CBusError {
+            operation: "read OUT_X_MSB on 0x1d".to_string(),
+            source: io::Error::from(io::ErrorKind::TimedOut).into(),
+        };
+
+        assert_eq!(uut.kind(), "i2c_bus");
+        assert_eq!(uut.cause().as_deref(), Some("timed_out"));
+        assert_eq!(
+            AccelerometerError::InvalidInputDataError(String::new()).cause(),
+            None
+        );
+    }
+
+    #[test]
+    fn display_identity_mismatch_for_chip_family() {
+        let uut = AccelerometerError::IdentityMismatch {
+            chip: SupportedChips::LSM6DSOX,
+            address: 0x6a,
+            expected: &[0x6c, 0x69, 0x6a],
+            actual: 0x00,
+        };
+
+        assert_eq!(
+            uut.to_string(),
+            "LSM6DSOX at 0x6a reported identity 0x00, expected 0x6c or 0x69 or 0x6a"
+        );
+    }
+
+    #[test]
+    fn display_unsupported_scale() {
+        let uut = AccelerometerError::UnsupportedScale {
+            chip: SupportedChips::M845xQ,
+            scale: Scale::SixteenG,
+        };
+
+        assert_eq!(uut.to_string(), "Scale SixteenG is not supported by M845xQ");
+        assert!(uut.source().is_none());
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::SupportedChips;
+
+/// Liveness and readiness of the measurement loop, shared with the HTTP
+/// listener (see [crate::http]) for `/healthz` and `/readyz`
+pub struct Health {
+    /// How long the loop may go without a [Health::heartbeat] before it is
+    /// considered stalled
+    stall_timeout: Duration,
+
+    state: Mutex<HealthState>,
+}
+
+#[derive(Default)]
+struct HealthState {
+    chip: Option<SupportedChips>,
+
+    address: Option<u8>,
+
+    last_heartbeat: Option<SystemTime>,
+
+    last_successful_read: Option<SystemTime>,
+
+    /// Whether the latest attempt to read the chip succeeded
+    chip_responding: bool,
+
+    mqtt_connected: bool,
+}
+
+/// The JSON body of a `/healthz` or `/readyz` response
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// Whether the check passed
+    pub ok: bool,
+
+    pub chip: Option<SupportedChips>,
+
+    pub address: Option<u8>,
+
+    /// Time of the latest successful read (RFC 3339)
+    pub last_successful_read: Option<String>,
+
+    /// Time since the measurement loop last ran, in seconds
+    pub loop_age_seconds: Option<f64>,
+
+    pub chip_responding: bool,
+
+    pub mqtt_connected: bool,
+}
+
+impl Health {
+    pub fn new(stall_timeout: Duration) -> Self {
+        Health {
+            stall_timeout,
+            state: Default::default(),
+        }
+    }
+
+    /// Records the identity of the chip being read
+    pub fn set_device(&self, chip: SupportedChips, address: u8) {
+        let mut state = self.state.lock().unwrap();
+
+        state.chip = Some(chip);
+        state.address = Some(address);
+    }
+
+    /// Records that the measurement loop is running (and whether it is
+    /// connected to the MQTT broker)
+    pub fn heartbeat(&self, now: SystemTime, mqtt_connected: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        state.last_heartbeat = Some(now);
+        state.mqtt_connected = mqtt_connected;
+    }
+
+    /// Records the outcome of reading the chip
+    pub fn record_read(&self, now: SystemTime, success: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        state.chip_responding = success;
+        if success {
+            state.last_successful_read = Some(now);
+        }
+    }
+
+    /// Passes while the measurement loop has run within the stall timeout
+    pub fn liveness(&self, now: SystemTime) -> HealthReport {
+        let mut report = self.report(now);
+
+        report.ok = report
+            .loop_age_seconds
+            .map_or(false, |age| age <= self.stall_timeout.as_secs_f64());
+
+        report
+    }
+
+    /// Passes while the loop is alive, the chip is responding and the MQTT
+    /// broker is connected
+    pub fn readiness(&self, now: SystemTime) -> HealthReport {
+        let mut report = self.liveness(now);
+
+        report.ok = report.ok && report.chip_responding && report.mqtt_connected;
+
+        report
+    }
+
+    fn report(&self, now: SystemTime) -> HealthReport {
+        let state = self.state.lock().unwrap();
+
+        HealthReport {
+            ok: false,
+            chip: state.chip,
+            address: state.address,
+            last_successful_read: state
+                .last_successful_read
+                .map(|time| DateTime::<Utc>::from(time).to_rfc3339()),
+            loop_age_seconds: state
+                .last_heartbeat
+                .map(|time| now.duration_since(time).unwrap_or_default().as_secs_f64()),
+            chip_responding: state.chip_responding,
+            mqtt_connected: state.mqtt_connected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::Health;
+    use crate::SupportedChips;
+
+    const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn not_alive_before_first_heartbeat() {
+        let uut = Health::new(STALL_TIMEOUT);
+
+        assert!(!uut.liveness(SystemTime::now()).ok);
+    }
+
+    #[test]
+    fn stalled_loop_is_not_alive() {
+        let start = SystemTime::now();
+        let uut = Health::new(STALL_TIMEOUT);
+
+        uut.heartbeat(start, true);
+
+        assert!(uut.liveness(start + Duration::from_secs(5)).ok);
+        assert!(!uut.liveness(start + Duration::from_secs(11)).ok);
+    }
+
+    #[test]
+    fn ready_requires_chip_and_mqtt() {
+        let start = SystemTime::now();
+        let uut = Health::new(STALL_TIMEOUT);
+        uut.set_device(SupportedChips::ICM20948, 0x68);
+
+        uut.heartbeat(start, false);
+        uut.record_read(start, true);
+        assert!(!uut.readiness(start).ok);
+
+        uut.heartbeat(start, true);
+        assert!(uut.readiness(start).ok);
+
+        uut.record_read(start, false);
+        let report = uut.readiness(start);
+        assert!(!report.ok);
+        assert_eq!(report.chip, Some(SupportedChips::ICM20948));
+        assert!(report.last_successful_read.is_some());
+    }
+}
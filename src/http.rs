@@ -0,0 +1,62 @@
+use std::{
+    io::{self, Cursor},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::SystemTime,
+};
+
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::{
+    health::{Health, HealthReport},
+    metrics::Metrics,
+};
+
+/// Serves [Metrics] at `/metrics`, and [Health] at `/healthz` (liveness) and
+/// `/readyz` (readiness), on `address` (e.g, 0.0.0.0:9898), from a
+/// background thread
+pub fn serve(
+    address: &str,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+) -> io::Result<JoinHandle<()>> {
+    let server = Server::http(address).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+    log::info!(target: "acclrmtr", "Serving metrics and health on http://{}", address);
+
+    thread::Builder::new()
+        .name("http".to_string())
+        .spawn(move || {
+            for request in server.incoming_requests() {
+                respond(request, &metrics, &health);
+            }
+        })
+}
+
+fn respond(request: Request, metrics: &Metrics, health: &Health) {
+    let now = SystemTime::now();
+
+    let response = match request.url() {
+        "/healthz" => health_response(health.liveness(now)),
+        "/readyz" => health_response(health.readiness(now)),
+        "/metrics" => Response::from_string(metrics.render(now)).with_header(
+            "Content-Type: text/plain; version=0.0.4"
+                .parse::<Header>()
+                .unwrap(),
+        ),
+        _ => Response::from_string("Not found").with_status_code(404),
+    };
+
+    if let Err(e) = request.respond(response) {
+        log::warn!(target: "acclrmtr", "Unable to respond to HTTP request: {}", e);
+    }
+}
+
+/// The report as JSON, with a 503 status if the check failed
+fn health_response(report: HealthReport) -> Response<Cursor<Vec<u8>>> {
+    let status = if report.ok { 200 } else { 503 };
+
+    Response::from_string(serde_json::to_string(&report).unwrap())
+        .with_status_code(status)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap())
+}
@@ -0,0 +1,81 @@
+use std::sync::mpsc;
+
+use gpio_cdev::{Chip, EventRequestFlags, LineEventHandle, LineRequestFlags};
+
+use crate::{AccelerometerError, AccelerometerResult, InterruptConfig};
+
+/// A source of data-ready signals, e.g. a GPIO line wired to the chip's
+/// interrupt pin.  Chips are configured for an active-high interrupt, so an
+/// implementation signals on each rising edge.
+pub trait DataReadyLine {
+    /// Blocks until the chip signals that a new sample is ready
+    fn wait_for_edge(&mut self) -> AccelerometerResult<()>;
+}
+
+/// A [DataReadyLine] backed by a Linux GPIO character device
+pub struct GpioDataReadyLine {
+    events: LineEventHandle,
+}
+
+impl GpioDataReadyLine {
+    pub fn new(config: &InterruptConfig) -> AccelerometerResult<Self> {
+        let gpio_error = |source| AccelerometerError::GpioError {
+            operation: format!("request line {} on {}", config.line, config.gpio_chip),
+            source,
+        };
+
+        let events = Chip::new(&config.gpio_chip)
+            .and_then(|mut chip| chip.get_line(config.line))
+            .and_then(|line| {
+                line.events(
+                    LineRequestFlags::INPUT,
+                    EventRequestFlags::RISING_EDGE,
+                    "accelerometer",
+                )
+            })
+            .map_err(gpio_error)?;
+
+        log::info!(target: "acclrmtr", "Data-ready line:    {} line {}", config.gpio_chip, config.line);
+
+        Ok(GpioDataReadyLine { events })
+    }
+}
+
+impl DataReadyLine for GpioDataReadyLine {
+    fn wait_for_edge(&mut self) -> AccelerometerResult<()> {
+        self.events
+            .get_event()
+            .map_err(|source| AccelerometerError::GpioError {
+                operation: format!(
+                    "wait for data-ready edge on line {}",
+                    self.events.line().offset()
+                ),
+                source,
+            })
+            .and(Ok(()))
+    }
+}
+
+/// A [DataReadyLine] for tests (or simulation), signalled by sending on the
+/// paired [mpsc::Sender]
+pub struct FakeDataReadyLine {
+    edges: mpsc::Receiver<()>,
+}
+
+impl FakeDataReadyLine {
+    pub fn new() -> (Self, mpsc::Sender<()>) {
+        let (sender, edges) = mpsc::channel();
+
+        (FakeDataReadyLine { edges }, sender)
+    }
+}
+
+impl DataReadyLine for FakeDataReadyLine {
+    fn wait_for_edge(&mut self) -> AccelerometerResult<()> {
+        self.edges.recv().map_err(|_| {
+            AccelerometerError::InvalidInputDataError(
+                "Fake data-ready line disconnected".to_string(),
+            )
+        })
+    }
+}
@@ -3,11 +3,17 @@ use serde::{Deserialize, Serialize};
 use std::{cell::RefCell, time::SystemTime};
 use strum::EnumString;
 
-use crate::chips::AccelerometerChip;
+use crate::{chips::AccelerometerChip, interrupt::DataReadyLine};
 
 pub mod accelerometer;
 pub mod chips;
+mod error;
+pub mod health;
+pub mod http;
+pub mod interrupt;
+pub mod metrics;
 pub mod mqtt;
+pub mod schedule;
 mod utils;
 mod value;
 
@@ -20,6 +26,7 @@ pub struct DeviceConfig {
     /// Address of PCA9685 (e.g, 0x40)
     pub address: Option<u8>,
 
+    /// Chip type, or [SupportedChips::Auto] to probe the I²C bus
     pub chip: SupportedChips,
 
     #[serde(default)]
@@ -29,6 +36,33 @@ pub struct DeviceConfig {
     #[serde(default)]
     /// Output data rate
     pub data_rate: OutputDataRate,
+
+    #[serde(default)]
+    /// Gyroscope full scale range (for chips with a gyroscope)
+    pub gyro_scale: GyroScale,
+
+    #[serde(default)]
+    /// Poll the auxiliary magnetometer (e.g, the ICM-20948's AK09916)
+    pub magnetometer: bool,
+
+    #[serde(default)]
+    /// Buffer samples in the chip's hardware FIFO (see [Accelerometer::drain_fifo])
+    pub fifo: bool,
+
+    #[serde(default)]
+    /// GPIO line wired to the chip's data-ready interrupt (see
+    /// [Accelerometer::wait_for_data_ready])
+    pub interrupt: Option<InterruptConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+/// A GPIO line wired to the chip's (first) interrupt pin
+pub struct InterruptConfig {
+    /// Path to GPIO character device (e.g, /dev/gpiochip0)
+    pub gpio_chip: String,
+
+    /// Offset of the line on the GPIO chip (e.g, 17)
+    pub line: u32,
 }
 
 pub struct AccelerometerConfig {}
@@ -45,27 +79,97 @@ pub struct Value {
 #[derive(Debug)]
 pub enum AccelerometerError {
     /// I²C bus error
-    I2CBusError(LinuxI2CError),
-
-    NotSupportedByChip,
+    I2CBusError {
+        /// The failed operation (e.g, "write CTRL_REG1 on 0x1d")
+        operation: String,
+        source: LinuxI2CError,
+    },
+
+    /// The I²C device file could not be opened
+    DeviceFileError { path: String, source: LinuxI2CError },
+
+    /// No supported chip responded while probing for [SupportedChips::Auto]
+    ChipNotDetected { path: String },
+
+    /// The chip's identity (WHO_AM_I) register does not hold an expected value
+    IdentityMismatch {
+        chip: SupportedChips,
+        address: u8,
+
+        /// The identities accepted for (members of the family of) the chip
+        expected: &'static [u8],
+        actual: u8,
+    },
+
+    /// A register does not read back the value the chip was configured with
+    RegisterMismatch {
+        chip: SupportedChips,
+        address: u8,
+        register: String,
+        expected: u8,
+        actual: u8,
+    },
+
+    /// The configured [Scale] is not supported by the chip
+    UnsupportedScale { chip: SupportedChips, scale: Scale },
+
+    /// The requested operation (e.g, "temperature readout") is not supported by the chip
+    NotSupportedByChip {
+        chip: SupportedChips,
+        operation: String,
+    },
+
+    /// GPIO error
+    GpioError {
+        /// The failed operation (e.g, "request line 17 on /dev/gpiochip0")
+        operation: String,
+        source: gpio_cdev::Error,
+    },
 
     /// Invalid input data provided
-    InvalidInputDataError,
+    InvalidInputDataError(String),
 }
 
-#[derive(Debug, EnumString, Deserialize, Clone)]
+#[derive(Debug, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum SupportedChips {
     #[strum(ascii_case_insensitive)]
     M845xQ,
 
     #[strum(ascii_case_insensitive)]
     ICM20948,
+
+    #[strum(ascii_case_insensitive)]
+    ADXL345,
+
+    /// ST's LIS3DH (and the register-compatible LIS2DH12)
+    #[strum(ascii_case_insensitive, serialize = "LIS3DH", serialize = "LIS2DH12")]
+    #[serde(alias = "LIS2DH12")]
+    LIS3DH,
+
+    /// InvenSense's MPU-6050 (and the register-compatible MPU-9250)
+    #[strum(ascii_case_insensitive, serialize = "MPU6050", serialize = "MPU9250")]
+    #[serde(alias = "MPU9250")]
+    MPU6050,
+
+    #[strum(ascii_case_insensitive)]
+    BMI160,
+
+    /// ST's LSM6DSOX (and the register-compatible LSM6DS3)
+    #[strum(ascii_case_insensitive, serialize = "LSM6DSOX", serialize = "LSM6DS3")]
+    #[serde(alias = "LSM6DS3")]
+    LSM6DSOX,
+
+    /// Detect the chip by probing the default address(es) of each supported
+    /// chip and reading its identity (WHO_AM_I) register
+    #[strum(ascii_case_insensitive)]
+    #[serde(alias = "auto")]
+    Auto,
 }
 
 /// Customized [Result], where the error type is [AccelerometerError]
 pub type AccelerometerResult<T> = Result<T, AccelerometerError>;
 
-#[derive(Debug, EnumString, Deserialize, Clone, Copy)]
+#[derive(Debug, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum Scale {
     #[strum(ascii_case_insensitive)]
     TwoG,
@@ -80,7 +184,23 @@ pub enum Scale {
     SixteenG,
 }
 
-#[derive(Debug, EnumString, Deserialize, Clone, Copy)]
+/// Gyroscope full scale range, in degrees per second
+#[derive(Debug, EnumString, Serialize, Deserialize, Clone, Copy)]
+pub enum GyroScale {
+    #[strum(ascii_case_insensitive)]
+    Dps250,
+
+    #[strum(ascii_case_insensitive)]
+    Dps500,
+
+    #[strum(ascii_case_insensitive)]
+    Dps1000,
+
+    #[strum(ascii_case_insensitive)]
+    Dps2000,
+}
+
+#[derive(Debug, EnumString, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum OutputDataRate {
     #[strum(ascii_case_insensitive)]
     DataRate800Hz = 0b000,
@@ -108,6 +228,9 @@ pub enum OutputDataRate {
 }
 
 pub struct Accelerometer {
+    /// The configuration in effect (see [Accelerometer::config])
+    config: DeviceConfig,
+
     /// The concrete I²C device implementation.
     chip: Box<dyn AccelerometerChip>,
 
@@ -116,6 +239,9 @@ pub struct Accelerometer {
     clock: Box<dyn utils::Clock>,
 
     zero: Option<Value>,
+
+    /// Signals when the chip has a new sample (if an interrupt is configured)
+    data_ready: Option<RefCell<Box<dyn DataReadyLine>>>,
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -126,6 +252,15 @@ pub struct AccelerometerMeasurement {
     /// Acceleration, in m/s²
     pub acceleration: Value,
 
+    /// Angular rate, in rad/s (for chips with a gyroscope)
+    pub angular_rate: Option<Value>,
+
+    /// Magnetic field, in µT (for chips with an enabled magnetometer)
+    pub magnetic_field: Option<Value>,
+
+    /// On-die temperature, in °C (for chips with a temperature sensor)
+    pub temperature_c: Option<f64>,
+
     /// Estimated velocity, in m/s
     pub estimated_velocity: Option<Value>,
 }
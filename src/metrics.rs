@@ -0,0 +1,273 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{AccelerometerError, AccelerometerMeasurement, Value};
+
+/// Weight of the newest sample interval in the achieved sample rate
+const SAMPLE_RATE_SMOOTHING: f64 = 0.1;
+
+/// Operational metrics of the measurement loop, shared with the HTTP
+/// listener (see [crate::http]) and rendered in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    samples_read: u64,
+
+    publish_successes: u64,
+
+    publish_failures: u64,
+
+    /// Errors reading the chip, by [AccelerometerError::kind] and
+    /// [AccelerometerError::cause]
+    errors: BTreeMap<(&'static str, Option<String>), u64>,
+
+    /// Time at which the latest sample was collected
+    last_sample_time: Option<SystemTime>,
+
+    /// Exponentially smoothed rate at which samples are read, in Hz
+    sample_rate_hz: Option<f64>,
+
+    last_acceleration: Option<Value>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn record_sample(&self, sample: &AccelerometerMeasurement) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(interval) = state
+            .last_sample_time
+            .and_then(|previous| sample.time.duration_since(previous).ok())
+            .filter(|interval| !interval.is_zero())
+        {
+            let rate_hz = 1.0 / interval.as_secs_f64();
+
+            state.sample_rate_hz = Some(match state.sample_rate_hz {
+                Some(previous) => previous + SAMPLE_RATE_SMOOTHING * (rate_hz - previous),
+                None => rate_hz,
+            });
+        }
+
+        state.samples_read += 1;
+        state.last_sample_time = Some(sample.time);
+        state.last_acceleration = Some(sample.acceleration);
+    }
+
+    pub fn record_error(&self, error: &AccelerometerError) {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .errors
+            .entry((error.kind(), error.cause()))
+            .or_insert(0) += 1;
+    }
+
+    /// Records the outcome of publishing one message
+    pub fn record_publish(&self, published: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        if published {
+            state.publish_successes += 1;
+        } else {
+            state.publish_failures += 1;
+        }
+    }
+
+    /// Time since the latest sample was collected (if any has been)
+    pub fn last_sample_age(&self, now: SystemTime) -> Option<Duration> {
+        self.state
+            .lock()
+            .unwrap()
+            .last_sample_time
+            .map(|time| now.duration_since(time).unwrap_or_default())
+    }
+
+    /// The metrics, in the Prometheus text exposition format
+    pub fn render(&self, now: SystemTime) -> String {
+        let age = self.last_sample_age(now);
+        let state = self.state.lock().unwrap();
+        let mut text = String::new();
+
+        write_metric(
+            &mut text,
+            "accelerometer_samples_read_total",
+            "counter",
+            "Samples read from the chip",
+            &[("", state.samples_read as f64)],
+        );
+
+        write_metric(
+            &mut text,
+            "accelerometer_publish_total",
+            "counter",
+            "Messages published (or not) to the MQTT broker",
+            &[
+                ("result=\"success\"", state.publish_successes as f64),
+                ("result=\"failure\"", state.publish_failures as f64),
+            ],
+        );
+
+        let errors: Vec<_> = state
+            .errors
+            .iter()
+            .map(|((kind, cause), count)| {
+                let labels = match cause {
+                    Some(cause) => format!("kind=\"{}\",cause=\"{}\"", kind, cause),
+                    None => format!("kind=\"{}\"", kind),
+                };
+
+                (labels, *count as f64)
+            })
+            .collect();
+        write_metric(
+            &mut text,
+            "accelerometer_errors_total",
+            "counter",
+            "Errors reading the chip, by kind (and cause, for bus errors)",
+            &errors,
+        );
+
+        if let Some(age) = age {
+            write_metric(
+                &mut text,
+                "accelerometer_last_sample_age_seconds",
+                "gauge",
+                "Time since the latest sample was collected",
+                &[("", age.as_secs_f64())],
+            );
+        }
+
+        if let Some(rate_hz) = state.sample_rate_hz {
+            write_metric(
+                &mut text,
+                "accelerometer_sample_rate_hertz",
+                "gauge",
+                "Achieved (smoothed) rate at which samples are read",
+                &[("", rate_hz)],
+            );
+        }
+
+        if let Some(acceleration) = state.last_acceleration {
+            write_metric(
+                &mut text,
+                "accelerometer_acceleration_meters_per_second_squared",
+                "gauge",
+                "Latest acceleration, by axis",
+                &[
+                    ("axis=\"x\"", acceleration.x),
+                    ("axis=\"y\"", acceleration.y),
+                    ("axis=\"z\"", acceleration.z),
+                ],
+            );
+        }
+
+        text
+    }
+}
+
+/// Appends one metric family (`labels` is a, possibly empty, label set
+/// without braces)
+fn write_metric<L: AsRef<str>>(
+    text: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(L, f64)],
+) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+
+    for (labels, value) in samples {
+        let labels = labels.as_ref();
+
+        if labels.is_empty() {
+            let _ = writeln!(text, "{} {}", name, value);
+        } else {
+            let _ = writeln!(text, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        time::{Duration, SystemTime},
+    };
+
+    use super::Metrics;
+    use crate::{AccelerometerError, AccelerometerMeasurement, Value};
+
+    fn sample(time: SystemTime, x: f64) -> AccelerometerMeasurement {
+        AccelerometerMeasurement {
+            acceleration: Value { x, y: 0.0, z: 9.8 },
+            ..AccelerometerMeasurement::new_default(time)
+        }
+    }
+
+    #[test]
+    fn render_counters_and_gauges() {
+        let start = SystemTime::now();
+        let uut = Metrics::new();
+
+        uut.record_sample(&sample(start, 0.1));
+        uut.record_sample(&sample(start + Duration::from_millis(20), 0.2));
+        uut.record_publish(true);
+        uut.record_publish(false);
+        uut.record_error(&AccelerometerError::InvalidInputDataError(String::new()));
+
+        let text = uut.render(start + Duration::from_millis(520));
+
+        assert!(text.contains("accelerometer_samples_read_total 2\n"));
+        assert!(text.contains("accelerometer_publish_total{result=\"success\"} 1\n"));
+        assert!(text.contains("accelerometer_publish_total{result=\"failure\"} 1\n"));
+        assert!(text.contains("accelerometer_errors_total{kind=\"invalid_input_data\"} 1\n"));
+        assert!(text.contains("accelerometer_last_sample_age_seconds 0.5\n"));
+        assert!(text.contains("accelerometer_sample_rate_hertz 50\n"));
+        assert!(
+            text.contains("accelerometer_acceleration_meters_per_second_squared{axis=\"x\"} 0.2\n")
+        );
+    }
+
+    #[test]
+    fn render_bus_errors_by_cause() {
+        let bus_error = |kind: io::ErrorKind| AccelerometerError::I2CBusError {
+            operation: "read OUT_X_MSB on 0x1d".to_string(),
+            source: io::Error::from(kind).into(),
+        };
+        let uut = Metrics::new();
+
+        uut.record_error(&bus_error(io::ErrorKind::TimedOut));
+        uut.record_error(&bus_error(io::ErrorKind::TimedOut));
+        uut.record_error(&bus_error(io::ErrorKind::NotFound));
+
+        let text = uut.render(SystemTime::now());
+
+        assert!(
+            text.contains("accelerometer_errors_total{kind=\"i2c_bus\",cause=\"timed_out\"} 2\n")
+        );
+        assert!(
+            text.contains("accelerometer_errors_total{kind=\"i2c_bus\",cause=\"not_found\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn render_without_samples() {
+        let text = Metrics::new().render(SystemTime::now());
+
+        assert!(text.contains("accelerometer_samples_read_total 0\n"));
+        assert!(!text.contains("accelerometer_last_sample_age_seconds"));
+    }
+}
@@ -0,0 +1,257 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{mqtt::BatchConfig, AccelerometerMeasurement};
+
+/// A window of evenly spaced acceleration samples, published as one message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeasurementBatch {
+    /// Time at which the first sample was collected
+    pub start: SystemTime,
+
+    /// Time between samples, in seconds
+    pub sample_period: f64,
+
+    /// Acceleration along each axis, in m/s²
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub z: Vec<f64>,
+}
+
+/// Any payload published on the measurement topic
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MeasurementPayload {
+    Batch(MeasurementBatch),
+
+    Single(AccelerometerMeasurement),
+}
+
+/// Accumulates samples into [MeasurementBatch]es according to a [BatchConfig]
+pub struct BatchAccumulator {
+    config: BatchConfig,
+
+    sample_period: Duration,
+
+    batch: Option<MeasurementBatch>,
+}
+
+impl MeasurementBatch {
+    fn new(start: SystemTime, sample_period: Duration) -> Self {
+        MeasurementBatch {
+            start,
+            sample_period: sample_period.as_secs_f64(),
+            x: Vec::new(),
+            y: Vec::new(),
+            z: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.is_empty()
+    }
+}
+
+impl BatchAccumulator {
+    pub fn new(config: &BatchConfig, sample_period: Duration) -> Self {
+        BatchAccumulator {
+            config: config.clone(),
+            sample_period,
+            batch: None,
+        }
+    }
+
+    /// Adds a sample, returning the batch if it completes one
+    pub fn push(&mut self, measurement: &AccelerometerMeasurement) -> Option<MeasurementBatch> {
+        let batch = self
+            .batch
+            .get_or_insert_with(|| MeasurementBatch::new(measurement.time, self.sample_period));
+
+        batch.x.push(measurement.acceleration.x);
+        batch.y.push(measurement.acceleration.y);
+        batch.z.push(measurement.acceleration.z);
+
+        if self.is_complete() {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Marks a gap in the samples (e.g, a failed read or a missed tick),
+    /// returning the pending batch, if any.
+    ///
+    /// NOTE: A batch's sample times are implied by its start and sample
+    ///       period, so a batch can't span a gap; the next sample starts a
+    ///       new one
+    pub fn gap(&mut self) -> Option<MeasurementBatch> {
+        self.flush()
+    }
+
+    /// Returns the (possibly partial) batch, if any samples are pending
+    pub fn flush(&mut self) -> Option<MeasurementBatch> {
+        self.batch.take()
+    }
+
+    fn is_complete(&self) -> bool {
+        let count = self.batch.as_ref().map_or(0, MeasurementBatch::len);
+
+        let full = self
+            .config
+            .max_samples
+            .map_or(false, |max_samples| count >= max_samples);
+
+        // NOTE: N samples span N - 1 sample periods
+        let window_elapsed = self.config.max_window.map_or(false, |max_window| {
+            self.sample_period.as_secs_f64() * count.saturating_sub(1) as f64 >= max_window
+        });
+
+        full || window_elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{BatchAccumulator, MeasurementPayload};
+    use crate::{mqtt::BatchConfig, AccelerometerMeasurement, Value};
+
+    const SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+
+    fn measurements(count: usize) -> Vec<AccelerometerMeasurement> {
+        let start = SystemTime::now();
+
+        (0..count)
+            .map(|index| AccelerometerMeasurement {
+                acceleration: Value {
+                    x: index as f64,
+                    y: -(index as f64),
+                    z: 9.81,
+                },
+                ..AccelerometerMeasurement::new_default(start + SAMPLE_PERIOD * index as u32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_by_sample_count() {
+        let config = BatchConfig {
+            max_samples: Some(3),
+            max_window: None,
+        };
+        let samples = measurements(4);
+
+        let mut uut = BatchAccumulator::new(&config, SAMPLE_PERIOD);
+
+        assert!(uut.push(&samples[0]).is_none());
+        assert!(uut.push(&samples[1]).is_none());
+
+        let batch = uut.push(&samples[2]).unwrap();
+        assert_eq!(batch.start, samples[0].time);
+        assert_eq!(batch.sample_period, 0.01);
+        assert_eq!(batch.x, vec![0.0, 1.0, 2.0]);
+        assert_eq!(batch.y, vec![0.0, -1.0, -2.0]);
+        assert_eq!(batch.z, vec![9.81, 9.81, 9.81]);
+
+        assert!(uut.push(&samples[3]).is_none());
+        assert_eq!(uut.flush().unwrap().start, samples[3].time);
+    }
+
+    #[test]
+    fn batch_by_window() {
+        let config = BatchConfig {
+            max_samples: Some(100),
+            max_window: Some(0.04),
+        };
+        let samples = measurements(5);
+
+        let mut uut = BatchAccumulator::new(&config, SAMPLE_PERIOD);
+
+        for sample in &samples[0..4] {
+            assert!(uut.push(sample).is_none());
+        }
+
+        // 5 samples span 4 sample periods (0.04 s)
+        assert_eq!(uut.push(&samples[4]).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn gap_closes_batch() {
+        let config = BatchConfig {
+            max_samples: Some(3),
+            max_window: None,
+        };
+        let samples = measurements(5);
+
+        let mut uut = BatchAccumulator::new(&config, SAMPLE_PERIOD);
+
+        assert!(uut.push(&samples[0]).is_none());
+        assert!(uut.push(&samples[1]).is_none());
+
+        // samples[2] was dropped, so samples[3] starts a new batch
+        assert_eq!(uut.gap().unwrap().x, vec![0.0, 1.0]);
+        assert!(uut.push(&samples[3]).is_none());
+        assert!(uut.push(&samples[4]).is_none());
+        let pending = uut.flush().unwrap();
+        assert_eq!(pending.start, samples[3].time);
+        assert_eq!(pending.x, vec![3.0, 4.0]);
+    }
+
+    #[test]
+    fn jitter_keeps_batch() {
+        let config = BatchConfig {
+            max_samples: Some(4),
+            max_window: None,
+        };
+        let mut samples = measurements(4);
+
+        // Read late (or early) by more than half a sample period, but on
+        // schedule
+        samples[1].time += SAMPLE_PERIOD * 3 / 4;
+        samples[2].time -= SAMPLE_PERIOD * 3 / 4;
+
+        let mut uut = BatchAccumulator::new(&config, SAMPLE_PERIOD);
+
+        for sample in &samples[0..3] {
+            assert!(uut.push(sample).is_none());
+        }
+
+        let batch = uut.push(&samples[3]).unwrap();
+        assert_eq!(batch.start, samples[0].time);
+        assert_eq!(batch.x, vec![0.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn decode_single_and_batch_payloads() {
+        let config = BatchConfig {
+            max_samples: Some(2),
+            max_window: None,
+        };
+        let samples = measurements(2);
+
+        let mut uut = BatchAccumulator::new(&config, SAMPLE_PERIOD);
+        uut.push(&samples[0]);
+        let batch = uut.push(&samples[1]).unwrap();
+
+        let single_json = serde_json::to_string(&samples[0]).unwrap();
+        let batch_json = serde_json::to_string(&batch).unwrap();
+
+        match serde_json::from_str(&single_json).unwrap() {
+            MeasurementPayload::Single(single) => {
+                assert_eq!(single.acceleration, samples[0].acceleration)
+            }
+            MeasurementPayload::Batch(_) => panic!("Decoded a single sample as a batch"),
+        }
+
+        match serde_json::from_str(&batch_json).unwrap() {
+            MeasurementPayload::Batch(decoded) => assert_eq!(decoded, batch),
+            MeasurementPayload::Single(_) => panic!("Decoded a batch as a single sample"),
+        }
+    }
+}
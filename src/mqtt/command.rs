@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mqtt::DeviceStatus, Accelerometer, AccelerometerError, AccelerometerResult, OutputDataRate,
+    Scale,
+};
+
+/// A command received on the command topic, e.g:
+///
+/// ```json
+/// {"id": "42", "command": "set_scale", "scale": "EightG"}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandRequest {
+    /// Echoed in the response, to correlate it with the request
+    #[serde(default)]
+    pub id: Option<String>,
+
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Re-measure the zero offset (see [Accelerometer::auto_set_zero])
+    Rezero,
+
+    SetScale {
+        scale: Scale,
+    },
+
+    SetDataRate {
+        data_rate: OutputDataRate,
+    },
+
+    /// Stop publishing measurements (commands are still handled)
+    Pause,
+
+    Resume,
+
+    /// Reply with the device's current [DeviceStatus]
+    GetConfig,
+}
+
+/// The reply published on the response topic: the device's status if the
+/// command succeeded, otherwise the error that prevented it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    pub success: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<DeviceStatus>,
+}
+
+impl CommandRequest {
+    /// Parses a command from a message payload
+    pub fn parse(payload: &str) -> AccelerometerResult<Self> {
+        serde_json::from_str(payload).map_err(|e| {
+            AccelerometerError::InvalidInputDataError(format!("Invalid command: {}", e))
+        })
+    }
+}
+
+impl Command {
+    /// Applies the command to `accelerometer`; `paused` is the service's
+    /// publishing state
+    pub fn apply(
+        &self,
+        accelerometer: &mut Accelerometer,
+        paused: &mut bool,
+    ) -> AccelerometerResult<()> {
+        match self {
+            Command::Rezero => accelerometer.auto_set_zero().map(|_| ()),
+            Command::SetScale { scale } => accelerometer.set_scale(*scale),
+            Command::SetDataRate { data_rate } => accelerometer.set_data_rate(*data_rate),
+            Command::Pause => {
+                *paused = true;
+                Ok(())
+            }
+            Command::Resume => {
+                *paused = false;
+                Ok(())
+            }
+            Command::GetConfig => Ok(()),
+        }
+    }
+}
+
+impl CommandResponse {
+    pub fn new(
+        id: Option<String>,
+        result: AccelerometerResult<()>,
+        accelerometer: &Accelerometer,
+    ) -> Self {
+        match result {
+            Ok(_) => CommandResponse {
+                id,
+                success: true,
+                error: None,
+                status: Some(DeviceStatus::online(accelerometer)),
+            },
+            Err(e) => CommandResponse {
+                id,
+                success: false,
+                error: Some(e.to_string()),
+                status: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, CommandRequest};
+    use crate::{AccelerometerError, Scale};
+
+    #[test]
+    fn parse_command_with_id() {
+        let uut = CommandRequest::parse(r#"{"id":"7","command":"set_scale","scale":"EightG"}"#);
+
+        assert!(matches!(
+            uut,
+            Ok(CommandRequest {
+                id: Some(ref id),
+                command: Command::SetScale {
+                    scale: Scale::EightG
+                },
+            }) if id == "7"
+        ));
+    }
+
+    #[test]
+    fn parse_command_without_arguments() {
+        let uut = CommandRequest::parse(r#"{"command":"rezero"}"#).unwrap();
+
+        assert_eq!(uut.id, None);
+        assert_eq!(uut.command, Command::Rezero);
+    }
+
+    #[test]
+    fn parse_unknown_command() {
+        let uut = CommandRequest::parse(r#"{"command":"self_destruct"}"#);
+
+        assert!(matches!(
+            uut,
+            Err(AccelerometerError::InvalidInputDataError(_))
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+
+use crate::mqtt::{QoS, QueuedMessage, TopicConfig, TopicContext};
+
+/// Publish Home Assistant MQTT discovery configs, so that the device shows up
+/// with sensors for each axis, the magnitude and the tilt of the acceleration
+#[derive(Debug, Deserialize, Clone)]
+pub struct HomeAssistantConfig {
+    #[serde(default = "default_discovery_prefix")]
+    /// Home Assistant's discovery prefix
+    pub discovery_prefix: String,
+
+    #[serde(default = "default_node_id")]
+    /// Identifies the device in Home Assistant (may use the same placeholders
+    /// as topics; see [TopicConfig])
+    pub node_id: String,
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_node_id() -> String {
+    "{hostname}_{chip}_{address}".to_string()
+}
+
+/// The latest acceleration in either a single measurement or a batch (see
+/// [crate::mqtt::MeasurementPayload])
+const ACCELERATION_TEMPLATE: &str = "{% set a = value_json.acceleration \
+    if value_json.acceleration is defined \
+    else {'x': value_json.x[-1], 'y': value_json.y[-1], 'z': value_json.z[-1]} %}";
+
+/// A Home Assistant MQTT sensor's discovery config
+#[derive(Debug, Serialize)]
+struct SensorConfig {
+    name: &'static str,
+
+    unique_id: String,
+
+    state_topic: String,
+
+    value_template: String,
+
+    unit_of_measurement: &'static str,
+
+    state_class: &'static str,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    availability: Vec<Availability>,
+
+    device: Device,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct Availability {
+    topic: String,
+
+    value_template: &'static str,
+
+    payload_available: &'static str,
+
+    payload_not_available: &'static str,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct Device {
+    identifiers: Vec<String>,
+
+    name: String,
+
+    model: String,
+
+    sw_version: &'static str,
+}
+
+impl HomeAssistantConfig {
+    /// The (retained) discovery config for each sensor.  Availability follows
+    /// the status topic's [crate::mqtt::DeviceStatus], if one is configured.
+    pub fn discovery_messages(
+        &self,
+        context: &TopicContext,
+        measurement_topic: &TopicConfig,
+        status_topic: Option<&TopicConfig>,
+    ) -> Vec<QueuedMessage> {
+        let node_id = sanitize(&context.render(&self.node_id));
+
+        let device = Device {
+            identifiers: vec![node_id.clone()],
+            name: format!(
+                "Accelerometer {} ({:#04x})",
+                context.hostname, context.address
+            ),
+            model: format!("{:?}", context.chip),
+            sw_version: env!("CARGO_PKG_VERSION"),
+        };
+
+        let availability: Vec<Availability> = status_topic
+            .map(|status_topic| Availability {
+                topic: status_topic.topic.clone(),
+                value_template: "{{ value_json.state }}",
+                payload_available: "online",
+                payload_not_available: "offline",
+            })
+            .into_iter()
+            .collect();
+
+        let sensors = [
+            ("x", "X acceleration", "m/s²", "a.x | round(3)"),
+            ("y", "Y acceleration", "m/s²", "a.y | round(3)"),
+            ("z", "Z acceleration", "m/s²", "a.z | round(3)"),
+            (
+                "magnitude",
+                "Acceleration magnitude",
+                "m/s²",
+                "((a.x ** 2 + a.y ** 2 + a.z ** 2) ** 0.5) | round(3)",
+            ),
+            (
+                "tilt",
+                "Tilt",
+                "°",
+                "(atan2((a.x ** 2 + a.y ** 2) ** 0.5, a.z) * 180 / pi) | round(2)",
+            ),
+        ];
+
+        sensors
+            .iter()
+            .map(|(object_id, name, unit, expression)| {
+                let config = SensorConfig {
+                    name,
+                    unique_id: format!("{}_{}", node_id, object_id),
+                    state_topic: measurement_topic.topic.clone(),
+                    value_template: format!("{}{{{{ {} }}}}", ACCELERATION_TEMPLATE, expression),
+                    unit_of_measurement: unit,
+                    state_class: "measurement",
+                    availability: availability.clone(),
+                    device: device.clone(),
+                };
+
+                QueuedMessage {
+                    topic: format!(
+                        "{}/sensor/{}/{}/config",
+                        self.discovery_prefix, node_id, object_id
+                    ),
+                    payload: serde_json::to_string(&config).unwrap(),
+                    qos: QoS::AtLeastOnce as i32,
+                    retain: true,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Home Assistant only accepts `[a-zA-Z0-9_-]` in node ids
+fn sanitize(node_id: &str) -> String {
+    node_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HomeAssistantConfig;
+    use crate::{
+        mqtt::{TopicConfig, TopicContext},
+        SupportedChips,
+    };
+
+    fn context() -> TopicContext {
+        TopicContext {
+            hostname: "rig.lab".to_string(),
+            chip: SupportedChips::M845xQ,
+            address: 0x1d,
+        }
+    }
+
+    fn config() -> HomeAssistantConfig {
+        serde_yaml::from_str("{}").unwrap()
+    }
+
+    fn topic(topic: &str) -> TopicConfig {
+        serde_yaml::from_str(topic).unwrap()
+    }
+
+    #[test]
+    fn discovery_topics() {
+        let uut = config().discovery_messages(&context(), &topic("accel/measurement"), None);
+
+        let topics: Vec<_> = uut.iter().map(|message| message.topic.as_str()).collect();
+
+        assert_eq!(
+            topics,
+            vec![
+                "homeassistant/sensor/rig_lab_M845xQ_0x1d/x/config",
+                "homeassistant/sensor/rig_lab_M845xQ_0x1d/y/config",
+                "homeassistant/sensor/rig_lab_M845xQ_0x1d/z/config",
+                "homeassistant/sensor/rig_lab_M845xQ_0x1d/magnitude/config",
+                "homeassistant/sensor/rig_lab_M845xQ_0x1d/tilt/config",
+            ]
+        );
+        assert!(uut.iter().all(|message| message.retain));
+    }
+
+    #[test]
+    fn discovery_payload() {
+        let uut = config().discovery_messages(
+            &context(),
+            &topic("accel/measurement"),
+            Some(&topic("accel/status")),
+        );
+
+        let payload: serde_json::Value = serde_json::from_str(&uut[0].payload).unwrap();
+
+        assert_eq!(payload["unique_id"], "rig_lab_M845xQ_0x1d_x");
+        assert_eq!(payload["state_topic"], "accel/measurement");
+        assert_eq!(payload["availability"][0]["topic"], "accel/status");
+        assert_eq!(payload["device"]["model"], "M845xQ");
+        assert!(payload["value_template"]
+            .as_str()
+            .unwrap()
+            .ends_with("{{ a.x | round(3) }}"));
+    }
+
+    #[test]
+    fn no_availability_without_status_topic() {
+        let uut = config().discovery_messages(&context(), &topic("accel/measurement"), None);
+
+        let payload: serde_json::Value = serde_json::from_str(&uut[0].payload).unwrap();
+
+        assert!(payload.get("availability").is_none());
+    }
+}
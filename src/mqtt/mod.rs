@@ -0,0 +1,398 @@
+use std::{env, fs, time::Duration};
+
+use dialoguer::{Input, Password};
+use serde::Deserialize;
+
+use crate::DeviceConfig;
+
+mod batch;
+mod command;
+mod discovery;
+mod offline;
+mod status;
+mod topic;
+
+pub use batch::{BatchAccumulator, MeasurementBatch, MeasurementPayload};
+pub use command::{Command, CommandRequest, CommandResponse};
+pub use discovery::HomeAssistantConfig;
+pub use offline::{OfflineQueue, QueuedMessage};
+pub use status::DeviceStatus;
+pub use topic::{TopicConfig, TopicContext};
+
+#[derive(Debug, Deserialize)]
+pub struct MqttBrokerConfig {
+    pub url: String,
+    /// Topic for measurements (or batches of them)
+    pub measurement_topic: TopicConfig,
+
+    #[serde(default)]
+    /// Topic for (always retained) [DeviceStatus] messages: "online" with
+    /// the device's details once connected, and "offline" (as the Last Will
+    /// and Testament) when the connection is lost
+    pub status_topic: Option<TopicConfig>,
+
+    #[serde(default)]
+    /// Topic to receive [CommandRequest]s on (e.g, to re-zero the device)
+    pub command_topic: Option<TopicConfig>,
+
+    #[serde(default)]
+    /// Topic for the [CommandResponse] to each command
+    pub response_topic: Option<TopicConfig>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+
+    #[serde(default)]
+    /// PEM file with the CA certificate(s) used to verify the broker
+    pub ca_file: Option<String>,
+
+    #[serde(default)]
+    /// PEM file with the client certificate, for mutual authentication
+    pub client_cert: Option<String>,
+
+    #[serde(default)]
+    /// PEM file with the client certificate's private key
+    pub client_key: Option<String>,
+
+    #[serde(default = "default_verify_hostname")]
+    /// Check that the broker's certificate matches its host name
+    pub verify_hostname: bool,
+
+    #[serde(default)]
+    /// Keep messages on disk while the broker is unreachable, and publish
+    /// them (in order) once the connection returns
+    pub offline_queue: Option<OfflineQueueConfig>,
+}
+
+fn default_verify_hostname() -> bool {
+    true
+}
+
+impl MqttBrokerConfig {
+    /// Whether to connect over TLS, as chosen by the URL's scheme (ssl:// or
+    /// mqtts://)
+    pub fn uses_tls(&self) -> bool {
+        self.url.starts_with("ssl://") || self.url.starts_with("mqtts://")
+    }
+
+    /// Whether any certificate (or key) is configured
+    pub fn has_certificates(&self) -> bool {
+        self.ca_file.is_some() || self.client_cert.is_some() || self.client_key.is_some()
+    }
+
+    /// TLS options for the connection (if [MqttBrokerConfig::uses_tls])
+    pub fn ssl_options(&self) -> paho_mqtt::Result<Option<paho_mqtt::SslOptions>> {
+        if !self.uses_tls() {
+            return Ok(None);
+        }
+
+        let mut builder = paho_mqtt::SslOptionsBuilder::new();
+
+        if let Some(ca_file) = self.ca_file.as_ref() {
+            builder.trust_store(ca_file)?;
+        }
+
+        if let Some(client_cert) = self.client_cert.as_ref() {
+            builder.key_store(client_cert)?;
+        }
+
+        if let Some(client_key) = self.client_key.as_ref() {
+            builder.private_key(client_key)?;
+        }
+
+        builder
+            .enable_server_cert_auth(true)
+            .verify(self.verify_hostname);
+
+        Ok(Some(builder.finalize()))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OfflineQueueConfig {
+    /// File holding the queued messages (e.g, /var/lib/accelerometer/queue.jsonl)
+    pub path: String,
+
+    /// Maximum number of queued messages; the oldest are dropped beyond this
+    pub max_messages: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServiceConfig {
+    pub mqtt_config: MqttBrokerConfig,
+
+    pub device_config: DeviceConfig,
+
+    #[serde(default)]
+    /// Time between published samples, in seconds (defaults to the
+    /// output data rate the chip actually runs at)
+    pub sample_interval: Option<f64>,
+
+    #[serde(default)]
+    /// Publish windows of samples as one message, rather than one message per sample
+    pub batch: Option<BatchConfig>,
+
+    #[serde(default)]
+    /// Announce the device's sensors to Home Assistant
+    pub home_assistant: Option<HomeAssistantConfig>,
+
+    #[serde(default)]
+    /// Address to serve Prometheus metrics (/metrics) and health checks
+    /// (/healthz, /readyz) on (e.g, 0.0.0.0:9898)
+    pub http_address: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+/// When to publish an accumulated [MeasurementBatch] (whichever comes first)
+pub struct BatchConfig {
+    /// Maximum number of samples per batch
+    pub max_samples: Option<usize>,
+
+    /// Maximum time spanned by a batch (from its first to its last sample),
+    /// in seconds
+    pub max_window: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum QoS {
+    #[default]
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl ServiceConfig {
+    pub fn load_from_file(path: &String, prompt_allowed: bool) -> ServiceConfig {
+        let config = fs::read_to_string(path).unwrap();
+        let mut config: ServiceConfig = serde_yaml::from_str(&config).unwrap();
+
+        if config
+            .sample_interval
+            .map_or(false, |interval| interval <= 0.0)
+        {
+            panic!("sample_interval must be > 0.0");
+        }
+
+        if let Some(batch) = config.batch.as_ref() {
+            if batch.max_samples.is_none() && batch.max_window.is_none() {
+                panic!("batch requires max_samples and/or max_window");
+            }
+
+            if batch.max_samples == Some(0) {
+                panic!("batch.max_samples must be > 0");
+            }
+
+            if batch
+                .max_window
+                .map_or(false, |max_window| max_window <= 0.0)
+            {
+                panic!("batch.max_window must be > 0.0");
+            }
+        }
+
+        if config.mqtt_config.client_cert.is_some() != config.mqtt_config.client_key.is_some() {
+            panic!("mqtt_config.client_cert and mqtt_config.client_key must be given together");
+        }
+
+        if config.mqtt_config.has_certificates() && !config.mqtt_config.uses_tls() {
+            panic!("mqtt_config certificates require an ssl:// or mqtts:// url");
+        }
+
+        config.mqtt_config.user.get_or_insert_with(|| {
+            env::var("MQTT_USERNAME").unwrap_or_else(|_| {
+                if prompt_allowed {
+                    Input::new()
+                        .with_prompt("MQTT username: ")
+                        .interact_text()
+                        .unwrap()
+                } else {
+                    panic!("No configuration value for mqtt_config.user and MQTT_USERNAME not present in environment")
+                }
+            })
+        });
+
+        config.mqtt_config.password.get_or_insert_with(|| {
+            env::var("MQTT_PASSWORD").unwrap_or_else(|_| {
+                if prompt_allowed {
+                    Password::new()
+                        .with_prompt("MQTT password: ")
+                        .interact()
+                        .unwrap()
+                } else {
+                    panic!("No configuration value for mqtt_config.password and MQTT_PASSWORD not present in environment")
+                }
+            })
+        });
+
+        config
+    }
+
+    /// Time between published samples, defaulting to the chip's
+    /// `sample_period` (see [crate::Accelerometer::sample_period])
+    pub fn sample_interval(&self, sample_period: Duration) -> Duration {
+        self.sample_interval
+            .map(Duration::from_secs_f64)
+            .unwrap_or(sample_period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::Path,
+        process::{self, Command, Stdio},
+        thread,
+        time::Duration,
+    };
+
+    use super::MqttBrokerConfig;
+
+    fn broker_config(yaml: &str) -> MqttBrokerConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn plain_tcp_by_default() {
+        let uut = broker_config("url: tcp://localhost:1883\nmeasurement_topic: m\n");
+
+        assert!(!uut.uses_tls());
+        assert!(uut.verify_hostname);
+        assert!(uut.ssl_options().unwrap().is_none());
+    }
+
+    #[test]
+    fn tls_from_url_scheme() {
+        let uut = broker_config("url: ssl://broker:8883\nmeasurement_topic: m\n");
+
+        assert!(uut.uses_tls());
+    }
+
+    #[test]
+    fn tls_with_certificates() {
+        let uut = broker_config(
+            "url: mqtts://broker:8883\n\
+             measurement_topic: m\n\
+             ca_file: /etc/ssl/ca.pem\n\
+             verify_hostname: false\n",
+        );
+
+        assert!(uut.uses_tls());
+        assert!(uut.has_certificates());
+        assert!(!uut.verify_hostname);
+    }
+
+    #[test]
+    fn certificates_do_not_imply_tls() {
+        let uut = broker_config(
+            "url: tcp://broker:1883\n\
+             measurement_topic: m\n\
+             ca_file: /etc/ssl/ca.pem\n",
+        );
+
+        assert!(uut.has_certificates());
+        assert!(!uut.uses_tls());
+    }
+
+    /// Generates a CA, and server and client certificates signed by it, with
+    /// the openssl CLI
+    fn generate_certificates(dir: &Path) {
+        let openssl = |args: String| {
+            let status = Command::new("openssl")
+                .args(args.split_whitespace())
+                .current_dir(dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .expect("openssl not found");
+            assert!(status.success(), "openssl {} failed", args);
+        };
+
+        fs::write(
+            dir.join("server.ext"),
+            "subjectAltName=DNS:localhost,IP:127.0.0.1\n",
+        )
+        .unwrap();
+
+        openssl(
+            "req -x509 -newkey rsa:2048 -nodes -days 1 -subj /CN=test-ca \
+             -keyout ca.key -out ca.pem"
+                .to_string(),
+        );
+
+        for (name, common_name, extensions) in [
+            ("server", "localhost", "-extfile server.ext"),
+            ("client", "client", ""),
+        ] {
+            openssl(format!(
+                "req -newkey rsa:2048 -nodes -subj /CN={common_name} \
+                 -keyout {name}.key -out {name}.csr"
+            ));
+            openssl(format!(
+                "x509 -req -days 1 -in {name}.csr -CA ca.pem -CAkey ca.key \
+                 -CAcreateserial -out {name}.pem {extensions}"
+            ));
+        }
+    }
+
+    /// Connects over mutual TLS to a local mosquitto (as a stand-in for the
+    /// real broker), using freshly generated certificates. Needs the openssl
+    /// and mosquitto binaries: `cargo test -- --ignored tls_connection`
+    #[test]
+    #[ignore]
+    fn tls_connection_to_local_broker() {
+        const PORT: u16 = 18883;
+
+        let dir = env::temp_dir().join(format!("accelerometer-tls-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        generate_certificates(&dir);
+
+        fs::write(
+            dir.join("mosquitto.conf"),
+            format!(
+                "listener {} 127.0.0.1\n\
+                 cafile ca.pem\n\
+                 certfile server.pem\n\
+                 keyfile server.key\n\
+                 require_certificate true\n\
+                 allow_anonymous true\n",
+                PORT
+            ),
+        )
+        .unwrap();
+
+        let mut broker = Command::new("mosquitto")
+            .args(["-c", "mosquitto.conf"])
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("mosquitto not found");
+        thread::sleep(Duration::from_millis(500));
+
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+        let uut = broker_config(&format!(
+            "url: ssl://localhost:{}\n\
+             measurement_topic: m\n\
+             ca_file: {}\n\
+             client_cert: {}\n\
+             client_key: {}\n",
+            PORT,
+            path("ca.pem"),
+            path("client.pem"),
+            path("client.key")
+        ));
+
+        let client = paho_mqtt::Client::new(uut.url.as_str()).unwrap();
+        let mut conn_opts = paho_mqtt::ConnectOptionsBuilder::new();
+        conn_opts
+            .connect_timeout(Duration::from_secs(3))
+            .ssl_options(uut.ssl_options().unwrap().unwrap());
+        let connected = client.connect(conn_opts.finalize());
+
+        broker.kill().unwrap();
+        broker.wait().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        connected.unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mqtt::OfflineQueueConfig;
+
+/// A message that could not be published while the broker was unreachable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub topic: String,
+
+    pub payload: String,
+
+    pub qos: i32,
+
+    #[serde(default)]
+    pub retain: bool,
+}
+
+/// A bounded, file-backed FIFO of [QueuedMessage]s, stored as one JSON
+/// object per line.  Once full, the oldest message is dropped to make room.
+///
+/// NOTE: Pushes are appended to the file; removals (and drops) only happen
+///       in memory until [OfflineQueue::sync] rewrites it.  The file is also
+///       compacted whenever it grows to twice the queue's capacity.
+pub struct OfflineQueue {
+    path: PathBuf,
+
+    max_messages: usize,
+
+    messages: VecDeque<QueuedMessage>,
+
+    /// Number of lines currently in the file
+    file_lines: usize,
+
+    /// Number of messages dropped because the queue was full
+    dropped: usize,
+}
+
+impl OfflineQueue {
+    /// Opens the queue at `config.path`, restoring any messages left by a
+    /// previous run (and skipping lines that cannot be parsed, e.g. one
+    /// truncated by a power loss)
+    pub fn open(config: &OfflineQueueConfig) -> io::Result<Self> {
+        let path = PathBuf::from(&config.path);
+        let mut messages = VecDeque::new();
+        let mut file_lines = 0;
+
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    file_lines += 1;
+
+                    match serde_json::from_str(&line) {
+                        Ok(message) => messages.push_back(message),
+                        Err(e) => log::warn!(target: "acclrmtr",
+                            "Skipping unreadable entry in {}: {}",
+                            path.display(),
+                            e
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut queue = OfflineQueue {
+            path,
+            max_messages: config.max_messages,
+            messages,
+            file_lines,
+            dropped: 0,
+        };
+
+        while queue.messages.len() > queue.max_messages {
+            queue.messages.pop_front();
+            queue.dropped += 1;
+        }
+
+        if queue.messages.len() != queue.file_lines {
+            queue.sync()?;
+        }
+
+        Ok(queue)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Number of messages dropped (since opening) because the queue was full
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Appends a message, dropping the oldest one if the queue is full
+    pub fn push(&mut self, message: QueuedMessage) -> io::Result<()> {
+        if self.max_messages == 0 {
+            self.dropped += 1;
+            return Ok(());
+        }
+
+        if self.messages.len() == self.max_messages {
+            self.messages.pop_front();
+            self.dropped += 1;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&message)?)?;
+
+        self.messages.push_back(message);
+        self.file_lines += 1;
+
+        if self.file_lines >= 2 * self.max_messages {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// The oldest message in the queue
+    pub fn front(&self) -> Option<&QueuedMessage> {
+        self.messages.front()
+    }
+
+    /// Removes the oldest message (e.g. once it has been published)
+    pub fn pop_front(&mut self) -> Option<QueuedMessage> {
+        self.messages.pop_front()
+    }
+
+    /// Rewrites the file to hold exactly the queued messages.  The new
+    /// contents are written to a temporary file which then replaces the
+    /// original, so a crash leaves either the old or the new queue.
+    pub fn sync(&mut self) -> io::Result<()> {
+        let temporary = self.path.with_extension("tmp");
+
+        {
+            let mut file = BufWriter::new(File::create(&temporary)?);
+
+            for message in self.messages.iter() {
+                writeln!(file, "{}", serde_json::to_string(message)?)?;
+            }
+
+            file.into_inner()?.sync_all()?;
+        }
+
+        fs::rename(&temporary, &self.path)?;
+        self.file_lines = self.messages.len();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{OfflineQueue, QueuedMessage};
+    use crate::mqtt::OfflineQueueConfig;
+
+    fn config(name: &str, max_messages: usize) -> OfflineQueueConfig {
+        let path = env::temp_dir().join(format!("acclrmtr-{}-{}.jsonl", name, process::id()));
+        let _ = fs::remove_file(&path);
+
+        OfflineQueueConfig {
+            path: path.to_string_lossy().into_owned(),
+            max_messages,
+        }
+    }
+
+    fn message(payload: &str) -> QueuedMessage {
+        QueuedMessage {
+            topic: "test/measurement".to_string(),
+            payload: payload.to_string(),
+            qos: 0,
+            retain: false,
+        }
+    }
+
+    fn payloads(queue: &mut OfflineQueue) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop_front())
+            .map(|message| message.payload)
+            .collect()
+    }
+
+    #[test]
+    fn restores_messages_in_order() {
+        let config = config("restore", 10);
+
+        let mut uut = OfflineQueue::open(&config).unwrap();
+        uut.push(message("1")).unwrap();
+        uut.push(message("2")).unwrap();
+        uut.push(message("3")).unwrap();
+        uut.pop_front();
+        uut.sync().unwrap();
+
+        let mut uut = OfflineQueue::open(&config).unwrap();
+        assert_eq!(payloads(&mut uut), vec!["2", "3"]);
+
+        fs::remove_file(&config.path).unwrap();
+    }
+
+    #[test]
+    fn drops_oldest_when_full() {
+        let config = config("full", 3);
+
+        let mut uut = OfflineQueue::open(&config).unwrap();
+        for payload in ["1", "2", "3", "4", "5", "6", "7"] {
+            uut.push(message(payload)).unwrap();
+        }
+
+        assert_eq!(uut.dropped(), 4);
+
+        // The file holds more than max_messages entries until it is compacted
+        let mut restored = OfflineQueue::open(&config).unwrap();
+        assert_eq!(payloads(&mut restored), vec!["5", "6", "7"]);
+        assert_eq!(payloads(&mut uut), vec!["5", "6", "7"]);
+
+        fs::remove_file(&config.path).unwrap();
+    }
+
+    #[test]
+    fn skips_truncated_entry() {
+        let config = config("truncated", 10);
+        fs::write(
+            &config.path,
+            "{\"topic\":\"t\",\"payload\":\"1\",\"qos\":0}\n{\"topic\":\"t\",\"pay",
+        )
+        .unwrap();
+
+        let mut uut = OfflineQueue::open(&config).unwrap();
+        assert_eq!(payloads(&mut uut), vec!["1"]);
+
+        fs::remove_file(&config.path).unwrap();
+    }
+}
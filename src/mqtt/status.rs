@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Accelerometer, OutputDataRate, Scale, SupportedChips, Value};
+
+/// The payload published (retained) on the status topic: a birth message
+/// describing the device once connected, and (as the MQTT Last Will and
+/// Testament) an offline message if the connection is lost
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum DeviceStatus {
+    Online {
+        chip: SupportedChips,
+
+        address: u8,
+
+        scale: Scale,
+
+        data_rate: OutputDataRate,
+
+        /// Offset subtracted from each measurement, in m/s²
+        zero: Option<Value>,
+
+        /// Version of the service
+        version: String,
+    },
+
+    Offline,
+}
+
+impl DeviceStatus {
+    pub fn online(accelerometer: &Accelerometer) -> Self {
+        let config = accelerometer.config();
+
+        DeviceStatus::Online {
+            chip: accelerometer.chip_type(),
+            address: accelerometer.address(),
+            scale: config.scale,
+            data_rate: config.data_rate,
+            zero: accelerometer.zero(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceStatus;
+    use crate::{OutputDataRate, Scale, SupportedChips};
+
+    #[test]
+    fn serialize_offline() {
+        assert_eq!(
+            serde_json::to_string(&DeviceStatus::Offline).unwrap(),
+            r#"{"state":"offline"}"#
+        );
+    }
+
+    #[test]
+    fn serialize_online() {
+        let uut = DeviceStatus::Online {
+            chip: SupportedChips::M845xQ,
+            address: 0x1d,
+            scale: Scale::TwoG,
+            data_rate: OutputDataRate::DataRate50Hz,
+            zero: None,
+            version: "1.1.0".to_string(),
+        };
+
+        assert_eq!(
+            serde_json::to_string(&uut).unwrap(),
+            r#"{"state":"online","chip":"M845xQ","address":29,"scale":"TwoG","data_rate":"DataRate50Hz","zero":null,"version":"1.1.0"}"#
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::{
+    mqtt::{QoS, QueuedMessage},
+    Accelerometer, SupportedChips,
+};
+
+/// A topic to publish to, with the QoS and retain flag for its messages.
+///
+/// The topic may contain the placeholders `{hostname}`, `{chip}` and
+/// `{address}` (see [TopicContext]), so that several boards can share one
+/// configuration file.  A plain string is accepted in place of the full
+/// form, and publishes at [QoS::AtMostOnce] without retaining.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(from = "TopicConfigRepr")]
+pub struct TopicConfig {
+    pub topic: String,
+
+    pub qos: QoS,
+
+    pub retain: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TopicConfigRepr {
+    Topic(String),
+
+    Full {
+        topic: String,
+
+        #[serde(default)]
+        qos: QoS,
+
+        #[serde(default)]
+        retain: bool,
+    },
+}
+
+impl From<TopicConfigRepr> for TopicConfig {
+    fn from(repr: TopicConfigRepr) -> Self {
+        match repr {
+            TopicConfigRepr::Topic(topic) => TopicConfig {
+                topic,
+                qos: QoS::default(),
+                retain: false,
+            },
+            TopicConfigRepr::Full { topic, qos, retain } => TopicConfig { topic, qos, retain },
+        }
+    }
+}
+
+/// The values substituted for a [TopicConfig]'s placeholders
+pub struct TopicContext {
+    /// The host's name, from /proc/sys/kernel/hostname
+    pub hostname: String,
+
+    pub chip: SupportedChips,
+
+    pub address: u8,
+}
+
+impl TopicContext {
+    pub fn new(accelerometer: &Accelerometer) -> Self {
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|hostname| hostname.trim().to_string())
+            .unwrap_or_else(|e| {
+                log::warn!(target: "acclrmtr", "Unable to read hostname: {}", e);
+                "unknown".to_string()
+            });
+
+        TopicContext {
+            hostname,
+            chip: accelerometer.chip_type(),
+            address: accelerometer.address(),
+        }
+    }
+
+    /// `template`, with its placeholders replaced
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{hostname}", &self.hostname)
+            .replace("{chip}", &format!("{:?}", self.chip))
+            .replace("{address}", &format!("{:#04x}", self.address))
+    }
+}
+
+impl TopicConfig {
+    /// This topic, with its placeholders replaced by the values in `context`
+    pub fn resolve(&self, context: &TopicContext) -> TopicConfig {
+        TopicConfig {
+            topic: context.render(&self.topic),
+            ..self.clone()
+        }
+    }
+
+    /// A message carrying `payload` to this topic
+    pub fn message(&self, payload: String) -> QueuedMessage {
+        QueuedMessage {
+            topic: self.topic.clone(),
+            payload,
+            qos: self.qos as i32,
+            retain: self.retain,
+        }
+    }
+
+    /// A subscription filter matching this topic for any placeholder values:
+    /// each level containing a placeholder becomes the `+` wildcard
+    pub fn subscription_filter(&self) -> String {
+        self.topic
+            .split('/')
+            .map(|level| {
+                if level.contains('{') && level.contains('}') {
+                    "+"
+                } else {
+                    level
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TopicConfig, TopicContext};
+    use crate::{mqtt::QoS, SupportedChips};
+
+    fn context() -> TopicContext {
+        TopicContext {
+            hostname: "rig-3".to_string(),
+            chip: SupportedChips::ICM20948,
+            address: 0x69,
+        }
+    }
+
+    #[test]
+    fn deserialize_plain_topic() {
+        let uut: TopicConfig = serde_yaml::from_str("sensors/accel").unwrap();
+
+        assert_eq!(
+            uut,
+            TopicConfig {
+                topic: "sensors/accel".to_string(),
+                qos: QoS::AtMostOnce,
+                retain: false,
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_full_topic() {
+        let uut: TopicConfig =
+            serde_yaml::from_str("topic: sensors/accel\nqos: AtLeastOnce\nretain: true\n").unwrap();
+
+        assert_eq!(uut.qos, QoS::AtLeastOnce);
+        assert!(uut.retain);
+    }
+
+    #[test]
+    fn resolve_placeholders() {
+        let uut: TopicConfig = serde_yaml::from_str("sensors/{hostname}/{chip}-{address}").unwrap();
+
+        assert_eq!(uut.resolve(&context()).topic, "sensors/rig-3/ICM20948-0x69");
+    }
+
+    #[test]
+    fn subscription_filter_replaces_placeholder_levels() {
+        let uut: TopicConfig =
+            serde_yaml::from_str("sensors/{hostname}/{chip}-{address}/measurement").unwrap();
+
+        assert_eq!(uut.subscription_filter(), "sensors/+/+/measurement");
+    }
+}
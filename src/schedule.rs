@@ -0,0 +1,170 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Fixed-rate scheduling against a monotonic clock.  Each deadline is a whole
+/// number of intervals after the first, so the time spent between ticks (and
+/// any oversleep) does not accumulate as drift.
+pub struct Ticker {
+    interval: Duration,
+
+    next_deadline: Instant,
+}
+
+impl Ticker {
+    /// Create a ticker whose first deadline is one `interval` from now
+    pub fn new(interval: Duration) -> Self {
+        Ticker {
+            interval,
+            next_deadline: Instant::now() + interval,
+        }
+    }
+
+    /// Sleeps until the next deadline, returning the number of deadlines
+    /// that had already passed (and were skipped) because the caller fell
+    /// behind
+    pub fn wait(&mut self) -> u32 {
+        let (delay, missed) = self.advance(Instant::now());
+
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+
+        missed
+    }
+
+    /// Moves to the deadline following `now`, returning the time remaining
+    /// until the current deadline (if it has not yet passed) and the number
+    /// of deadlines skipped
+    fn advance(&mut self, now: Instant) -> (Option<Duration>, u32) {
+        let deadline = self.next_deadline;
+
+        if now < deadline {
+            self.next_deadline = deadline + self.interval;
+
+            (Some(deadline - now), 0)
+        } else {
+            let missed = ((now - deadline).as_secs_f64() / self.interval.as_secs_f64()) as u32;
+            self.next_deadline = deadline + self.interval * (missed + 1);
+
+            (None, missed)
+        }
+    }
+}
+
+/// Exponential backoff between retries: the delay starts at `min` and
+/// doubles after each failure, up to `max`
+pub struct Backoff {
+    min: Duration,
+
+    max: Duration,
+
+    delay: Duration,
+
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Backoff {
+            min,
+            max,
+            delay: min,
+            next_attempt: None,
+        }
+    }
+
+    /// Whether the next attempt may be made at `now`
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next_attempt.map_or(true, |attempt| now >= attempt)
+    }
+
+    /// Records a failed attempt at `now`, returning the delay until the next
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        let delay = self.delay;
+
+        self.next_attempt = Some(now + delay);
+        self.delay = (delay * 2).min(self.max);
+
+        delay
+    }
+
+    /// Records a successful attempt; the next failure starts over at `min`
+    pub fn reset(&mut self) {
+        self.delay = self.min;
+        self.next_attempt = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Backoff, Ticker};
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn ticker_at(start: Instant) -> Ticker {
+        Ticker {
+            interval: INTERVAL,
+            next_deadline: start + INTERVAL,
+        }
+    }
+
+    #[test]
+    fn sleeps_until_deadline() {
+        let start = Instant::now();
+        let mut uut = ticker_at(start);
+
+        let (delay, missed) = uut.advance(start + Duration::from_millis(30));
+
+        assert_eq!(delay, Some(Duration::from_millis(70)));
+        assert_eq!(missed, 0);
+        assert_eq!(uut.next_deadline, start + INTERVAL * 2);
+    }
+
+    #[test]
+    fn does_not_drift() {
+        let start = Instant::now();
+        let mut uut = ticker_at(start);
+
+        // Wake 5 ms late for the first deadline; the second is unaffected
+        uut.advance(start + Duration::from_millis(30));
+        let (delay, _) = uut.advance(start + Duration::from_millis(105));
+
+        assert_eq!(delay, Some(Duration::from_millis(95)));
+    }
+
+    #[test]
+    fn skips_missed_deadlines() {
+        let start = Instant::now();
+        let mut uut = ticker_at(start);
+
+        let (delay, missed) = uut.advance(start + Duration::from_millis(350));
+
+        assert_eq!(delay, None);
+        assert_eq!(missed, 2);
+        assert_eq!(uut.next_deadline, start + INTERVAL * 4);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let start = Instant::now();
+        let mut uut = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+
+        assert!(uut.is_due(start));
+        assert_eq!(uut.failed(start), Duration::from_secs(1));
+        assert!(!uut.is_due(start + Duration::from_millis(500)));
+        assert!(uut.is_due(start + Duration::from_secs(1)));
+
+        assert_eq!(uut.failed(start), Duration::from_secs(2));
+        assert_eq!(uut.failed(start), Duration::from_secs(4));
+        assert_eq!(uut.failed(start), Duration::from_secs(5));
+
+        uut.reset();
+
+        assert!(uut.is_due(start));
+        assert_eq!(uut.failed(start), Duration::from_secs(1));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{AccelerometerMeasurement, DeviceConfig, OutputDataRate, Scale};
+use crate::{AccelerometerMeasurement, DeviceConfig, GyroScale, OutputDataRate, Scale};
 use chrono::{DateTime, Utc};
 use std::{
     fmt,
@@ -27,6 +27,12 @@ impl Default for Scale {
     }
 }
 
+impl Default for GyroScale {
+    fn default() -> Self {
+        GyroScale::Dps250
+    }
+}
+
 impl Default for OutputDataRate {
     fn default() -> Self {
         OutputDataRate::DataRate50Hz
@@ -38,6 +44,9 @@ impl AccelerometerMeasurement {
         AccelerometerMeasurement {
             time: time,
             acceleration: Default::default(),
+            angular_rate: None,
+            magnetic_field: None,
+            temperature_c: None,
             estimated_velocity: Default::default(),
         }
     }
@@ -92,7 +101,29 @@ impl fmt::Display for AccelerometerMeasurement {
             self.acceleration.x,
             self.acceleration.y,
             self.acceleration.z,
-        )
+        )?;
+
+        if let Some(rate) = self.angular_rate {
+            write!(
+                f,
+                " Gyro (rad/s): {:>9.5}, {:>9.5}, {:>9.5}",
+                rate.x, rate.y, rate.z
+            )?;
+        }
+
+        if let Some(field) = self.magnetic_field {
+            write!(
+                f,
+                " Mag. (µT): {:>9.5}, {:>9.5}, {:>9.5}",
+                field.x, field.y, field.z
+            )?;
+        }
+
+        if let Some(temperature) = self.temperature_c {
+            write!(f, " Temp. (°C): {:>6.2}", temperature)?;
+        }
+
+        Ok(())
     }
 }
 